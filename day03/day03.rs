@@ -1,5 +1,7 @@
-use std::collections::HashSet;
+use std::env;
+use std::fmt;
 use std::fs;
+use std::process;
 
 fn calculate_priority(name: &char) -> u32 {
     match name {
@@ -9,61 +11,596 @@ fn calculate_priority(name: &char) -> u32 {
     }
 }
 
-fn main() {
-    // let filename = "test.in";
-    let filename = "my.in";
-    let input = fs::read_to_string(filename).unwrap();
+/// Set of items, where bit `calculate_priority(item)` marks the item as present
+type ItemMask = u64;
 
-    {
-        let mut total_priority = 0;
+fn item_mask(items: &str) -> ItemMask {
+    items
+        .chars()
+        .map(|c| calculate_priority(&c))
+        .filter(|&p| p != 0)
+        .fold(0, |mask, p| mask | (1 << p))
+}
+
+fn mask_items(mask: ItemMask) -> impl Iterator<Item = char> {
+    ('a'..='z')
+        .chain('A'..='Z')
+        .filter(move |c| mask & (1 << calculate_priority(c)) != 0)
+}
 
-        for line in input.split('\n').filter(|l| !l.is_empty()) {
-            let set1: HashSet<_> = line.get(..line.len() / 2).unwrap().chars().collect();
-            let set2: HashSet<_> = line.get(line.len() / 2..).unwrap().chars().collect();
+fn mask_priority(mask: ItemMask) -> u32 {
+    (1..=52).filter(|p| mask & (1 << p) != 0).sum()
+}
 
-            let priority: u32 = set1
-                .intersection(&set2)
-                .into_iter()
-                .map(|name| calculate_priority(name))
-                .sum();
+#[derive(Debug)]
+enum BadgeError {
+    NoCommonItem {
+        first_line: usize,
+        last_line: usize,
+    },
+    MultipleCommonItems {
+        first_line: usize,
+        last_line: usize,
+        common: ItemMask,
+    },
+    IncompleteGroup {
+        first_line: usize,
+        size: usize,
+        expected: usize,
+    },
+}
 
-            total_priority += priority;
+impl fmt::Display for BadgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BadgeError::NoCommonItem {
+                first_line,
+                last_line,
+            } => write!(
+                f,
+                "lines {}-{}: group has no common item",
+                first_line, last_line
+            ),
+            BadgeError::MultipleCommonItems {
+                first_line,
+                last_line,
+                common,
+            } => {
+                let items: String = mask_items(*common).collect();
+                write!(
+                    f,
+                    "lines {}-{}: group has multiple common items: {}",
+                    first_line, last_line, items
+                )
+            }
+            BadgeError::IncompleteGroup {
+                first_line,
+                size,
+                expected,
+            } => write!(
+                f,
+                "line {}: last group has {} rucksacks, expected {}",
+                first_line, size, expected
+            ),
         }
+    }
+}
 
-        println!("Part 1:");
-        println!("{}", total_priority);
+fn sum_badge_priorities(
+    rucksacks: &[(usize, ItemMask)],
+    group_size: usize,
+) -> Result<u32, BadgeError> {
+    let mut total_priority = 0;
+
+    for group in rucksacks.chunks(group_size) {
+        let first_line = group[0].0;
+        let last_line = group[group.len() - 1].0;
+
+        if group.len() < group_size {
+            return Err(BadgeError::IncompleteGroup {
+                first_line,
+                size: group.len(),
+                expected: group_size,
+            });
+        }
+
+        let common = group.iter().fold(!0, |acc, (_, mask)| acc & mask);
+
+        match common.count_ones() {
+            0 => {
+                return Err(BadgeError::NoCommonItem {
+                    first_line,
+                    last_line,
+                })
+            }
+            1 => total_priority += common.trailing_zeros(),
+            _ => {
+                return Err(BadgeError::MultipleCommonItems {
+                    first_line,
+                    last_line,
+                    common,
+                })
+            }
+        }
     }
 
-    {
-        let mut total_priority = 0;
+    Ok(total_priority)
+}
+
+#[derive(Debug, Clone)]
+struct Rucksack {
+    line: usize,
+    compartments: [Vec<char>; 2],
+}
+
+impl Rucksack {
+    fn from_line(line: usize, items: &str) -> Rucksack {
+        let (half1, half2) = items.split_at(items.len() / 2);
+        Rucksack {
+            line,
+            compartments: [half1.chars().collect(), half2.chars().collect()],
+        }
+    }
+
+    fn duplicated_items(&self) -> ItemMask {
+        let [half1, half2] = &self.compartments;
+        item_mask(&half1.iter().collect::<String>()) & item_mask(&half2.iter().collect::<String>())
+    }
+
+    /// Copies of duplicated items in the compartment holding fewer of them, which is how
+    /// many items have to leave for no item to be duplicated
+    fn excess(&self) -> usize {
+        mask_items(self.duplicated_items())
+            .map(|item| self.item_excess(item))
+            .sum()
+    }
+
+    /// Copies of `item` in the compartment holding fewer of them
+    fn item_excess(&self, item: char) -> usize {
+        self.compartments
+            .iter()
+            .map(|c| c.iter().filter(|&&x| x == item).count())
+            .min()
+            .unwrap()
+    }
+
+    fn replace(&mut self, side: usize, old: char, new: char) {
+        let compartment = &mut self.compartments[side];
+        let i = compartment.iter().position(|&c| c == old).unwrap();
+        compartment[i] = new;
+    }
+}
+
+/// Item `item` taken out of compartment `side` of rucksack with index `rucksack`
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    rucksack: usize,
+    side: usize,
+    item: char,
+}
+
+#[derive(Debug)]
+struct Swap {
+    first: Slot,
+    second: Slot,
+}
+
+#[derive(Debug)]
+struct ReorganizationPlan {
+    swaps: Vec<Swap>,
+    lower_bound: usize,
+    /// Rucksacks after the swaps, which may still hold duplicated items
+    result: Vec<Rucksack>,
+    /// Whether no plan with fewer swaps exists
+    minimal: bool,
+    /// Whether no swaps at all remove every duplicated item
+    impossible: bool,
+}
+
+fn total_excess(rucksacks: &[Rucksack]) -> usize {
+    rucksacks.iter().map(Rucksack::excess).sum()
+}
+
+/// Excess copies of every item over all rucksacks, indexed by priority
+type ItemExcess = [usize; 53];
+
+fn excess_by_item(rucksacks: &[Rucksack]) -> ItemExcess {
+    let mut excess = [0; 53];
+    for rucksack in rucksacks {
+        for item in mask_items(rucksack.duplicated_items()) {
+            excess[calculate_priority(&item) as usize] += rucksack.item_excess(item);
+        }
+    }
+    excess
+}
+
+/// Lower bound on the swaps removing every excess copy. A swap takes one item out of
+/// each of two rucksacks, and the two items are different.
+fn swaps_needed(excess: &ItemExcess) -> usize {
+    let total: usize = excess.iter().sum();
+    total.div_ceil(2).max(*excess.iter().max().unwrap())
+}
+
+/// Every item in every compartment, once for each kind of item, as swapping any copy of
+/// an item out of a compartment has the same effect
+fn all_slots(rucksacks: &[Rucksack]) -> Vec<Slot> {
+    let mut slots = vec![];
+    for (r, rucksack) in rucksacks.iter().enumerate() {
+        for (side, compartment) in rucksack.compartments.iter().enumerate() {
+            let items: String = compartment.iter().collect();
+            for item in mask_items(item_mask(&items)) {
+                slots.push(Slot {
+                    rucksack: r,
+                    side,
+                    item,
+                });
+            }
+        }
+    }
+    slots
+}
+
+fn is_valid_swap(a: Slot, b: Slot) -> bool {
+    a.rucksack != b.rucksack && a.item != b.item
+}
+
+fn apply_swap(rucksacks: &mut [Rucksack], a: Slot, b: Slot) {
+    rucksacks[a.rucksack].replace(a.side, a.item, b.item);
+    rucksacks[b.rucksack].replace(b.side, b.item, a.item);
+}
+
+fn undo_swap(rucksacks: &mut [Rucksack], a: Slot, b: Slot) {
+    apply_swap(
+        rucksacks,
+        Slot { item: b.item, ..a },
+        Slot { item: a.item, ..b },
+    );
+}
+
+/// Applies the swap, updating the excess copies of the two items it moves, which are the
+/// only ones changing
+fn apply_counted_swap(state: &mut [Rucksack], excess: &mut ItemExcess, a: Slot, b: Slot) {
+    let count = |state: &[Rucksack], item: char| {
+        state[a.rucksack].item_excess(item) + state[b.rucksack].item_excess(item)
+    };
+    for item in [a.item, b.item] {
+        excess[calculate_priority(&item) as usize] -= count(state, item);
+    }
+    apply_swap(state, a, b);
+    for item in [a.item, b.item] {
+        excess[calculate_priority(&item) as usize] += count(state, item);
+    }
+}
+
+/// Applies the swap, returning how much it lowers the total excess. Only the two
+/// rucksacks taking part change.
+fn swap_gain(state: &mut [Rucksack], a: Slot, b: Slot) -> isize {
+    let excess = |state: &[Rucksack]| state[a.rucksack].excess() + state[b.rucksack].excess();
+    let before = excess(state);
+    apply_swap(state, a, b);
+    before as isize - excess(state) as isize
+}
+
+/// Repeatedly swaps a copy of a duplicated item with the partner lowering the total
+/// excess the most, until no swap lowers it
+fn improve_greedily(state: &mut [Rucksack]) -> Vec<Swap> {
+    let mut swaps = vec![];
 
-        let mut sets: Vec<HashSet<char>> = vec![];
+    loop {
+        let slots = all_slots(state);
+        let mut progress = false;
 
-        for line in input.split('\n').filter(|l| !l.is_empty()) {
-            if sets.len() < 3 {
-                sets.push(line.chars().collect());
+        for &a in &slots {
+            if state[a.rucksack].duplicated_items() & (1 << calculate_priority(&a.item)) == 0
+                || !state[a.rucksack].compartments[a.side].contains(&a.item)
+            {
+                continue;
             }
-            if sets.len() == 3 {
-                let mut common = sets[0].clone();
-                for set in sets.iter().skip(1) {
-                    common = common
-                        .intersection(&set)
-                        .map(|c| *c)
-                        .collect::<HashSet<char>>();
+
+            let mut best: Option<(isize, Slot)> = None;
+            for &b in &slots {
+                if !is_valid_swap(a, b) || !state[b.rucksack].compartments[b.side].contains(&b.item)
+                {
+                    continue;
+                }
+                let gain = swap_gain(state, a, b);
+                undo_swap(state, a, b);
+                if gain > best.map_or(0, |(g, _)| g) {
+                    best = Some((gain, b));
+                    if gain == 2 {
+                        break;
+                    }
+                }
+            }
+
+            if let Some((_, b)) = best {
+                apply_swap(state, a, b);
+                swaps.push(Swap {
+                    first: a,
+                    second: b,
+                });
+                progress = true;
+            }
+        }
+
+        if !progress {
+            return swaps;
+        }
+    }
+}
+
+/// Iterative deepening over every swap, pruned by the lower bound on the swaps still
+/// needed. Gives up after checking `limit` swaps.
+struct SwapSearch {
+    swaps: Vec<Swap>,
+    checked: usize,
+    limit: usize,
+}
+
+impl SwapSearch {
+    /// Returns whether every excess copy can be removed within `depth` more swaps, or
+    /// `None` once the limit is reached
+    fn search(
+        &mut self,
+        state: &mut [Rucksack],
+        excess: &mut ItemExcess,
+        depth: usize,
+    ) -> Option<bool> {
+        if excess.iter().all(|&e| e == 0) {
+            return Some(true);
+        }
+
+        let slots = all_slots(state);
+        for (i, &a) in slots.iter().enumerate() {
+            for &b in &slots[i + 1..] {
+                if !is_valid_swap(a, b) {
+                    continue;
+                }
+                self.checked += 1;
+                if self.checked > self.limit {
+                    return None;
+                }
+
+                apply_counted_swap(state, excess, a, b);
+                let found = if swaps_needed(excess) < depth {
+                    self.swaps.push(Swap {
+                        first: a,
+                        second: b,
+                    });
+                    let found = self.search(state, excess, depth - 1);
+                    if found != Some(true) {
+                        self.swaps.pop();
+                    }
+                    found
+                } else {
+                    Some(false)
+                };
+                if found != Some(true) {
+                    apply_counted_swap(
+                        state,
+                        excess,
+                        Slot { item: b.item, ..a },
+                        Slot { item: a.item, ..b },
+                    );
+                }
+                if found != Some(false) {
+                    return found;
                 }
+            }
+        }
+        Some(false)
+    }
+}
+
+/// Items with more copies than fit into the larger compartment of every rucksack, so
+/// some rucksack has to keep them in both compartments
+fn overfull_items(rucksacks: &[Rucksack]) -> ItemMask {
+    let room: usize = rucksacks
+        .iter()
+        .map(|r| r.compartments.iter().map(|c| c.len()).max().unwrap())
+        .sum();
+
+    let mut counts = [0; 53];
+    for rucksack in rucksacks {
+        for item in rucksack.compartments.iter().flatten() {
+            counts[calculate_priority(item) as usize] += 1;
+        }
+    }
+
+    (1..counts.len())
+        .filter(|&p| counts[p] > room)
+        .fold(0, |mask, p| mask | (1 << p))
+}
 
-                total_priority += calculate_priority(
-                    &common
-                        .into_iter()
-                        .next()
-                        .expect("There must be a common item in a 3-group"),
-                );
+/// Finds swaps between rucksacks which remove every duplicated item.
+///
+/// A swap exchanges any two different items from two rucksacks, and only the state after
+/// the last swap has to be free of duplicates. Every copy of a duplicated item in the
+/// compartment holding fewer of them has to leave, and a swap takes two different items
+/// out of two rucksacks, which gives the lower bound. A greedy plan is improved by an
+/// exact search over every swap, and the plan is only marked minimal if the search
+/// finishes. Swaps between rucksacks can rearrange the items in any way, taking at most
+/// three swaps per item, so the search also proves when no plan exists.
+fn plan_reorganization(rucksacks: &[Rucksack]) -> ReorganizationPlan {
+    let excess = excess_by_item(rucksacks);
+    let lower_bound = swaps_needed(&excess);
+
+    let mut plan = ReorganizationPlan {
+        swaps: vec![],
+        lower_bound,
+        result: rucksacks.to_vec(),
+        minimal: lower_bound == 0,
+        impossible: lower_bound != 0 && (rucksacks.len() < 2 || overfull_items(rucksacks) != 0),
+    };
+    if plan.minimal || plan.impossible {
+        return plan;
+    }
 
-                sets.clear();
+    plan.swaps = improve_greedily(&mut plan.result);
+    let solved = total_excess(&plan.result) == 0;
+    if solved && plan.swaps.len() == lower_bound {
+        plan.minimal = true;
+        return plan;
+    }
+
+    let max_depth = if solved {
+        plan.swaps.len() - 1
+    } else {
+        3 * rucksacks
+            .iter()
+            .flat_map(|r| &r.compartments)
+            .flatten()
+            .count()
+    };
+    let mut search = SwapSearch {
+        swaps: vec![],
+        checked: 0,
+        limit: 500_000,
+    };
+    for depth in lower_bound..=max_depth {
+        let mut state = rucksacks.to_vec();
+        match search.search(&mut state, &mut excess.clone(), depth) {
+            Some(true) => {
+                plan.swaps = search.swaps;
+                plan.result = state;
+                plan.minimal = true;
+                return plan;
             }
+            Some(false) => {}
+            None => return plan,
         }
+    }
+    plan.minimal = solved;
+    plan.impossible = !solved;
+    plan
+}
 
-        println!("Part 2:");
+fn print_reorganization_report(rucksacks: &[Rucksack]) {
+    println!("Duplicated items:");
+    for rucksack in rucksacks {
+        let duplicated = rucksack.duplicated_items();
+        if duplicated == 0 {
+            continue;
+        }
+        let items: String = mask_items(duplicated).collect();
+        println!("line {}: {}", rucksack.line, items);
+    }
+
+    let plan = plan_reorganization(rucksacks);
+
+    if plan.impossible {
+        println!("No swaps remove every duplicated item");
+        let overfull = overfull_items(rucksacks);
+        if overfull != 0 {
+            let items: String = mask_items(overfull).collect();
+            println!(
+                "too many copies to keep in one compartment per rucksack: {}",
+                items
+            );
+        }
+        return;
+    }
+
+    let remaining: Vec<&Rucksack> = plan
+        .result
+        .iter()
+        .filter(|r| r.duplicated_items() != 0)
+        .collect();
+    if plan.minimal {
+        println!("Suggested swaps ({}, fewest possible):", plan.swaps.len());
+    } else if remaining.is_empty() {
+        println!(
+            "Suggested swaps ({}, at least {} needed):",
+            plan.swaps.len(),
+            plan.lower_bound
+        );
+    } else {
+        println!(
+            "Suggested swaps ({}, some duplicated items remain):",
+            plan.swaps.len()
+        );
+    }
+    for swap in &plan.swaps {
+        println!(
+            "swap '{}' from line {} compartment {} with '{}' from line {} compartment {}",
+            swap.first.item,
+            rucksacks[swap.first.rucksack].line,
+            swap.first.side + 1,
+            swap.second.item,
+            rucksacks[swap.second.rucksack].line,
+            swap.second.side + 1
+        );
+    }
+    for rucksack in remaining {
+        let items: String = mask_items(rucksack.duplicated_items()).collect();
+        println!("still duplicated on line {}: {}", rucksack.line, items);
+    }
+}
+
+fn parse_group_size() -> usize {
+    let args: Vec<String> = env::args().collect();
+    match args.iter().position(|a| a == "--group-size") {
+        Some(i) => match args.get(i + 1).and_then(|n| n.parse().ok()) {
+            Some(n) if n > 0 => n,
+            _ => {
+                eprintln!("--group-size expects a positive number");
+                process::exit(1);
+            }
+        },
+        None => 3,
+    }
+}
+
+fn main() {
+    // let filename = "test.in";
+    let filename = "my.in";
+    let input = fs::read_to_string(filename).unwrap();
+
+    let group_size = parse_group_size();
+
+    let lines: Vec<(usize, &str)> = input
+        .split('\n')
+        .enumerate()
+        .map(|(i, l)| (i + 1, l))
+        .filter(|(_, l)| !l.is_empty())
+        .collect();
+
+    {
+        let total_priority: u32 = lines
+            .iter()
+            .map(|(_, line)| {
+                let (half1, half2) = line.split_at(line.len() / 2);
+                mask_priority(item_mask(half1) & item_mask(half2))
+            })
+            .sum();
+
+        println!("Part 1:");
         println!("{}", total_priority);
+
+        if env::args().any(|a| a == "--report") {
+            let rucksacks: Vec<Rucksack> = lines
+                .iter()
+                .map(|&(line_number, line)| Rucksack::from_line(line_number, line))
+                .collect();
+            print_reorganization_report(&rucksacks);
+        }
+    }
+
+    {
+        let rucksacks: Vec<(usize, ItemMask)> = lines
+            .iter()
+            .map(|&(line_number, line)| (line_number, item_mask(line)))
+            .collect();
+
+        match sum_badge_priorities(&rucksacks, group_size) {
+            Ok(total_priority) => {
+                println!("Part 2:");
+                println!("{}", total_priority);
+            }
+            Err(err) => {
+                eprintln!("Part 2 failed: {}", err);
+                process::exit(1);
+            }
+        }
     }
 }
@@ -1,21 +1,28 @@
+use std::env;
 use std::fs;
-use std::num::ParseIntError;
+use std::process;
 use std::str::FromStr;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 struct Range {
     begin: u32,
     end: u32,
 }
 
 impl FromStr for Range {
-    type Err = ParseIntError;
+    type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (begin_str, end_str) = s.split_once('-').unwrap();
+        let (begin_str, end_str) = s
+            .split_once('-')
+            .ok_or(format!("{}: expected BEGIN-END", s))?;
+
+        let begin: u32 = begin_str.parse().map_err(|e| format!("{}: {}", s, e))?;
+        let end: u32 = end_str.parse().map_err(|e| format!("{}: {}", s, e))?;
 
-        let begin: u32 = begin_str.parse()?;
-        let end: u32 = end_str.parse()?;
+        if begin > end {
+            return Err(format!("{}: begins after it ends", s));
+        }
 
         Ok(Range { begin, end })
     }
@@ -33,6 +40,134 @@ impl Range {
     }
 }
 
+fn parse_assignments(line: &str) -> Result<Vec<Range>, String> {
+    line.split(',').map(|s| s.parse()).collect()
+}
+
+#[derive(Debug)]
+struct SweepReport {
+    overlapping_pairs: usize,
+    max_coverage: usize,
+    max_coverage_section: u32,
+    /// Sections never assigned, starting from section 1
+    unassigned: Vec<Range>,
+}
+
+/// Sweeps over begins and ends of all the ranges in section order
+fn sweep(ranges: &[Range]) -> SweepReport {
+    // (section, is_begin); ends are placed right after the last section of a range,
+    // so at equal positions they sort before begins
+    let mut events: Vec<(u64, bool)> = ranges
+        .iter()
+        .flat_map(|r| vec![(r.begin as u64, true), (r.end as u64 + 1, false)])
+        .collect();
+    events.sort();
+
+    let mut report = SweepReport {
+        overlapping_pairs: 0,
+        max_coverage: 0,
+        max_coverage_section: 0,
+        unassigned: vec![],
+    };
+
+    let mut active = 0;
+    let mut uncovered_since: Option<u64> = Some(1);
+    for (section, is_begin) in events {
+        if is_begin {
+            if let Some(gap_begin) = uncovered_since.take() {
+                if gap_begin < section {
+                    report.unassigned.push(Range {
+                        begin: gap_begin as u32,
+                        end: section as u32 - 1,
+                    });
+                }
+            }
+            report.overlapping_pairs += active;
+            active += 1;
+            if active > report.max_coverage {
+                report.max_coverage = active;
+                report.max_coverage_section = section as u32;
+            }
+        } else {
+            active -= 1;
+            if active == 0 {
+                uncovered_since = Some(section);
+            }
+        }
+    }
+
+    report
+}
+
+#[derive(Debug)]
+struct Elf {
+    line: usize,
+    position: usize,
+    range: Range,
+}
+
+/// Picks the fewest elves whose assignments cover every section covered by all of them.
+/// Returns indices of the picked elves in section order.
+fn minimum_cover(elves: &[Elf]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..elves.len()).collect();
+    order.sort_by_key(|&i| elves[i].range.begin);
+
+    let mut kept: Vec<usize> = vec![];
+    let mut covered: Option<u32> = None;
+    let mut i = 0;
+
+    while i < order.len() {
+        let next_begin = elves[order[i]].range.begin;
+        let limit = match covered.map(|c| c.saturating_add(1)) {
+            Some(after) if next_begin <= after => after,
+            _ => next_begin,
+        };
+
+        let mut best = order[i];
+        while i < order.len() && elves[order[i]].range.begin <= limit {
+            if elves[order[i]].range.end > elves[best].range.end {
+                best = order[i];
+            }
+            i += 1;
+        }
+
+        if covered.is_none_or(|c| elves[best].range.end > c) {
+            kept.push(best);
+            covered = Some(elves[best].range.end);
+        }
+    }
+
+    kept
+}
+
+fn print_release_plan(elves: &[Elf]) {
+    let kept = minimum_cover(elves);
+    let mut is_kept = vec![false; elves.len()];
+    for &k in &kept {
+        is_kept[k] = true;
+    }
+
+    println!("Elves needed: {} of {}", kept.len(), elves.len());
+    println!("Can be released:");
+    for (i, elf) in elves.iter().enumerate() {
+        if is_kept[i] {
+            continue;
+        }
+        let container = kept
+            .iter()
+            .map(|&k| &elves[k])
+            .find(|other| other.range.contains(&elf.range));
+        let covered_by = match container {
+            Some(other) => format!("line {} elf {}", other.line, other.position),
+            None => "several elves".to_owned(),
+        };
+        println!(
+            "line {} elf {} ({}-{}), covered by {}",
+            elf.line, elf.position, elf.range.begin, elf.range.end, covered_by
+        );
+    }
+}
+
 fn main() {
     // let filename = "test.in";
     let filename = "my.in";
@@ -40,20 +175,41 @@ fn main() {
 
     let mut containing_count = 0;
     let mut overlappping_count = 0;
+    let mut all_ranges: Vec<Range> = vec![];
+    let mut elves: Vec<Elf> = vec![];
 
-    for line in input.split('\n').filter(|l| !l.is_empty()) {
-        let (range1_str, range2_str) = line.split_once(',').unwrap();
+    for (line_index, line) in input.split('\n').enumerate().filter(|(_, l)| !l.is_empty()) {
+        let ranges = match parse_assignments(line) {
+            Ok(ranges) => ranges,
+            Err(err) => {
+                eprintln!("line {}: {}", line_index + 1, err);
+                process::exit(1);
+            }
+        };
 
-        let range1: Range = range1_str.parse().unwrap();
-        let range2: Range = range2_str.parse().unwrap();
+        let pairs = || {
+            ranges
+                .iter()
+                .enumerate()
+                .flat_map(|(i, a)| ranges.iter().skip(i + 1).map(move |b| (a, b)))
+        };
 
-        if range1.contains(&range2) || range2.contains(&range1) {
+        if pairs().any(|(a, b)| a.contains(b) || b.contains(a)) {
             containing_count += 1;
         }
 
-        if range1.overlaps(&range2) {
+        if pairs().any(|(a, b)| a.overlaps(b)) {
             overlappping_count += 1;
         }
+
+        for (i, range) in ranges.iter().enumerate() {
+            elves.push(Elf {
+                line: line_index + 1,
+                position: i + 1,
+                range: *range,
+            });
+        }
+        all_ranges.extend(ranges);
     }
 
     println!("Part 1:");
@@ -61,4 +217,23 @@ fn main() {
 
     println!("Part 2:");
     println!("{}", overlappping_count);
+
+    let report = sweep(&all_ranges);
+
+    println!("Sweep:");
+    println!("overlapping pairs: {}", report.overlapping_pairs);
+    println!(
+        "max coverage: {} (at section {})",
+        report.max_coverage, report.max_coverage_section
+    );
+    let unassigned: Vec<String> = report
+        .unassigned
+        .iter()
+        .map(|r| format!("{}-{}", r.begin, r.end))
+        .collect();
+    println!("never assigned: {}", unassigned.join(","));
+
+    if env::args().any(|a| a == "--release") {
+        print_release_plan(&elves);
+    }
 }
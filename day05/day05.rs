@@ -1,7 +1,12 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::env;
+use std::fmt;
 use std::fs;
-use std::num::ParseIntError;
+use std::process;
 use std::str::FromStr;
 
+#[derive(Debug, Clone)]
 struct Command {
     move_count: u32,
     source: usize,
@@ -9,15 +14,24 @@ struct Command {
 }
 
 impl FromStr for Command {
-    type Err = ParseIntError;
+    type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let words: Vec<_> = s.split(' ').collect();
-        assert!(words.len() == 6);
+        if words.len() != 6 || words[0] != "move" || words[2] != "from" || words[4] != "to" {
+            return Err(format!("expected `move N from A to B`, got `{}`", s));
+        }
+
+        let parse_stack = |word: &str| -> Result<usize, String> {
+            match word.parse::<usize>().map_err(|e| e.to_string())? {
+                0 => Err("stacks are numbered from 1".to_owned()),
+                n => Ok(n - 1),
+            }
+        };
 
-        let move_count: u32 = words[1].parse()?;
-        let source: usize = words[3].parse::<usize>()? - 1;
-        let destination: usize = words[5].parse::<usize>()? - 1;
+        let move_count = words[1].parse::<u32>().map_err(|e| e.to_string())?;
+        let source = parse_stack(words[3])?;
+        let destination = parse_stack(words[5])?;
 
         Ok(Command {
             move_count,
@@ -27,17 +41,403 @@ impl FromStr for Command {
     }
 }
 
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "move {} from {} to {}",
+            self.move_count,
+            self.source + 1,
+            self.destination + 1
+        )
+    }
+}
+
+#[derive(Debug)]
+enum CraneError {
+    StackOutOfRange {
+        line: usize,
+        stack: usize,
+        stack_count: usize,
+    },
+    NotEnoughCrates {
+        line: usize,
+        stack: usize,
+        requested: u32,
+        available: usize,
+    },
+}
+
+impl fmt::Display for CraneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CraneError::StackOutOfRange {
+                line,
+                stack,
+                stack_count,
+            } => write!(
+                f,
+                "line {}: stack {} does not exist, there are {} stacks",
+                line,
+                stack + 1,
+                stack_count
+            ),
+            CraneError::NotEnoughCrates {
+                line,
+                stack,
+                requested,
+                available,
+            } => write!(
+                f,
+                "line {}: cannot move {} crates from stack {} holding {}",
+                line,
+                requested,
+                stack + 1,
+                available
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Crane {
+    /// Moves crates one at a time
+    CrateMover9000,
+    /// Moves all the crates of a command at once
+    CrateMover9001,
+    /// Moves up to the given number of crates at once
+    Capacity(usize),
+}
+
+impl Crane {
+    fn capacity(&self) -> usize {
+        match self {
+            Crane::CrateMover9000 => 1,
+            Crane::CrateMover9001 => usize::MAX,
+            Crane::Capacity(n) => *n,
+        }
+    }
+
+    fn apply(
+        &self,
+        stacks: &mut [Vec<char>],
+        line: usize,
+        cmd: &Command,
+    ) -> Result<(), CraneError> {
+        for &stack in &[cmd.source, cmd.destination] {
+            if stack >= stacks.len() {
+                return Err(CraneError::StackOutOfRange {
+                    line,
+                    stack,
+                    stack_count: stacks.len(),
+                });
+            }
+        }
+
+        let available = stacks[cmd.source].len();
+        if cmd.move_count as usize > available {
+            return Err(CraneError::NotEnoughCrates {
+                line,
+                stack: cmd.source,
+                requested: cmd.move_count,
+                available,
+            });
+        }
+
+        let mut remaining = cmd.move_count as usize;
+        while remaining > 0 {
+            let lifted = remaining.min(self.capacity());
+            let source = &mut stacks[cmd.source];
+            let buff = source.split_off(source.len() - lifted);
+            stacks[cmd.destination].extend(buff);
+            remaining -= lifted;
+        }
+
+        Ok(())
+    }
+
+    fn run(
+        &self,
+        stacks: &mut [Vec<char>],
+        commands: &[(usize, Command)],
+    ) -> Result<(), CraneError> {
+        for (line, cmd) in commands {
+            self.apply(stacks, *line, cmd)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the crate drawing, including the numbered footer line
+fn parse_drawing(lines: &[&str]) -> Vec<Vec<char>> {
+    let stack_count = lines.last().unwrap().trim().split("  ").count();
+
+    let mut stacks: Vec<Vec<char>> = vec![];
+    for _ in 1..=stack_count {
+        stacks.push(vec![]);
+    }
+
+    for line in lines.iter().rev().skip(1) {
+        line.char_indices().for_each(|(i, c)| {
+            if i % 4 == 1 {
+                let stack_index = (i - 1) / 4;
+                if c.is_alphabetic() {
+                    stacks[stack_index].push(c);
+                }
+            }
+        })
+    }
+
+    stacks
+}
+
+/// Renders stacks in the same format as the puzzle input drawing
+fn render_drawing(stacks: &[Vec<char>]) -> String {
+    let height = stacks.iter().map(|s| s.len()).max().unwrap_or(0);
+    let mut lines: Vec<String> = vec![];
+
+    for level in (0..height).rev() {
+        let cells: Vec<String> = stacks
+            .iter()
+            .map(|s| match s.get(level) {
+                Some(c) => format!("[{}]", c),
+                None => "   ".to_owned(),
+            })
+            .collect();
+        lines.push(cells.join(" "));
+    }
+
+    let footer: Vec<String> = (1..=stacks.len()).map(|i| format!(" {} ", i)).collect();
+    lines.push(footer.join(" "));
+
+    lines.join("\n")
+}
+
+type Stacks = Vec<Vec<char>>;
+
+/// What a crate rests on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Support {
+    Floor(usize),
+    Crate(char),
+}
+
+/// Sorted pairs of neighbouring crates, or of a stack's floor and its bottom crate. With
+/// `ordered` unset, pairs do not remember which crate is on top.
+fn neighbour_pairs(stacks: &[Vec<char>], ordered: bool) -> Vec<(Support, Support)> {
+    let mut pairs = vec![];
+    for (i, stack) in stacks.iter().enumerate() {
+        let mut below = Support::Floor(i);
+        for &c in stack {
+            let above = Support::Crate(c);
+            pairs.push(if ordered {
+                (below, above)
+            } else {
+                (below.min(above), below.max(above))
+            });
+            below = above;
+        }
+    }
+    pairs.sort();
+    pairs
+}
+
+/// Lower bound on the number of commands left.
+///
+/// Every stack which is not the bottom of its target has to be the source of a command,
+/// every stack which does not contain its target at the bottom has to be the destination
+/// of one, and a command has one of each. Also, a command only puts the bottom crate of
+/// every lifted batch on something new, so it creates at most `crate_count / capacity`
+/// of the missing target neighbour pairs. Moving crates one at a time only reverses them,
+/// so then the only new unordered pair is where the moved crates land.
+/// `wanted` holds the neighbour pairs of `target`.
+fn remaining_commands_bound(
+    stacks: &[Vec<char>],
+    target: &[Vec<char>],
+    wanted: &[(Support, Support)],
+    capacity: usize,
+) -> usize {
+    let sources = stacks
+        .iter()
+        .zip(target)
+        .filter(|(s, t)| !t.starts_with(s))
+        .count();
+    let destinations = stacks
+        .iter()
+        .zip(target)
+        .filter(|(s, t)| !s.starts_with(t))
+        .count();
+
+    let ordered = capacity != 1;
+    let current = neighbour_pairs(stacks, ordered);
+    let mut missing: usize = 0;
+    let mut i = 0;
+    for pair in wanted {
+        while i < current.len() && current[i] < *pair {
+            i += 1;
+        }
+        if i < current.len() && current[i] == *pair {
+            i += 1;
+        } else {
+            missing += 1;
+        }
+    }
+    let created_per_command = if capacity == 1 {
+        1
+    } else {
+        current.len().div_ceil(capacity).max(1)
+    };
+
+    sources
+        .max(destinations)
+        .max(missing.div_ceil(created_per_command))
+}
+
+struct PlanNode {
+    stacks: Stacks,
+    commands: usize,
+    /// Lower bound on the commands left to reach the target
+    bound: usize,
+    parent: Option<(usize, Command)>,
+}
+
+/// Finds a shortest sequence of commands transforming `start` into `target` using A*
+/// search over stack states, giving up after reaching `limit` distinct states
+fn plan_commands(
+    crane: Crane,
+    start: &[Vec<char>],
+    target: &[Vec<char>],
+    limit: usize,
+) -> Result<Vec<Command>, String> {
+    let sorted_crates = |stacks: &[Vec<char>]| {
+        let mut crates: Vec<char> = stacks.iter().flatten().cloned().collect();
+        crates.sort();
+        crates
+    };
+    if start.len() != target.len() || sorted_crates(start) != sorted_crates(target) {
+        return Err("target state cannot be reached".to_owned());
+    }
+
+    let capacity = crane.capacity();
+    let wanted = neighbour_pairs(target, capacity != 1);
+    let bound = |stacks: &[Vec<char>]| remaining_commands_bound(stacks, target, &wanted, capacity);
+
+    let mut nodes: Vec<PlanNode> = vec![PlanNode {
+        stacks: start.to_vec(),
+        commands: 0,
+        bound: bound(start),
+        parent: None,
+    }];
+    let mut indices: HashMap<Stacks, usize> = HashMap::new();
+    let mut queue: BinaryHeap<Reverse<(usize, usize)>> = BinaryHeap::new();
+
+    indices.insert(start.to_vec(), 0);
+    queue.push(Reverse((nodes[0].bound, 0)));
+
+    while let Some(Reverse((estimate, index))) = queue.pop() {
+        let commands = nodes[index].commands;
+        if estimate > commands + nodes[index].bound {
+            // Reached again through a shorter sequence after being queued
+            continue;
+        }
+
+        if nodes[index].stacks == target {
+            let mut plan = vec![];
+            let mut index = index;
+            while let Some((parent, cmd)) = nodes[index].parent.clone() {
+                plan.push(cmd);
+                index = parent;
+            }
+            plan.reverse();
+            return Ok(plan);
+        }
+
+        if nodes.len() > limit {
+            return Err(format!(
+                "no plan found after searching {} states, a plan needs at least {} commands",
+                limit, estimate
+            ));
+        }
+
+        let stacks = nodes[index].stacks.clone();
+        for source in 0..stacks.len() {
+            for destination in (0..stacks.len()).filter(|&d| d != source) {
+                for move_count in 1..=stacks[source].len() as u32 {
+                    let cmd = Command {
+                        move_count,
+                        source,
+                        destination,
+                    };
+                    let mut next = stacks.clone();
+                    crane.apply(&mut next, 0, &cmd).unwrap();
+
+                    let parent = Some((index, cmd));
+                    match indices.get(&next) {
+                        Some(&i) if nodes[i].commands <= commands + 1 => continue,
+                        Some(&i) => {
+                            nodes[i].commands = commands + 1;
+                            nodes[i].parent = parent;
+                            queue.push(Reverse((commands + 1 + nodes[i].bound, i)));
+                        }
+                        None => {
+                            let bound = bound(&next);
+                            indices.insert(next.clone(), nodes.len());
+                            queue.push(Reverse((commands + 1 + bound, nodes.len())));
+                            nodes.push(PlanNode {
+                                stacks: next,
+                                commands: commands + 1,
+                                bound,
+                                parent,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Err("target state cannot be reached".to_owned())
+}
+
+fn top_crates(stacks: &[Vec<char>]) -> String {
+    stacks.iter().filter_map(|s| s.last()).collect()
+}
+
+fn parse_capacity() -> Option<usize> {
+    let args: Vec<String> = env::args().collect();
+    let i = args.iter().position(|a| a == "--capacity")?;
+    match args.get(i + 1).and_then(|n| n.parse().ok()) {
+        Some(n) if n > 0 => Some(n),
+        _ => {
+            eprintln!("--capacity expects a positive number");
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_option(name: &str) -> Option<String> {
+    let args: Vec<String> = env::args().collect();
+    let i = args.iter().position(|a| a == name)?;
+    match args.get(i + 1) {
+        Some(value) => Some(value.clone()),
+        None => {
+            eprintln!("{} expects a value", name);
+            process::exit(1);
+        }
+    }
+}
+
 fn main() {
     // let filename = "test.in";
     let filename = "my.in";
     let input = fs::read_to_string(filename).unwrap();
 
     let mut stack_lines: Vec<&str> = vec![];
-    let mut command_lines: Vec<&str> = vec![];
+    let mut command_lines: Vec<(usize, &str)> = vec![];
 
     {
         let mut is_on_commands = false;
-        for line in input.split('\n') {
+        for (i, line) in input.split('\n').enumerate() {
             if line.is_empty() {
                 if is_on_commands {
                     break;
@@ -48,76 +448,100 @@ fn main() {
             }
 
             if is_on_commands {
-                command_lines.push(line);
+                command_lines.push((i + 1, line));
             } else {
                 stack_lines.push(line);
             }
         }
     }
 
-    let stack_count = stack_lines
-        .last()
-        .unwrap()
-        .trim()
-        .split("  ")
-        .into_iter()
-        .count();
-
-    // println!("There are {} stacks!", stack_count);
-
-    let mut orig_stacks: Vec<Vec<char>> = vec![];
-    for _ in 1..=stack_count {
-        orig_stacks.push(vec![]);
-    }
+    let orig_stacks = parse_drawing(&stack_lines);
 
-    for line in stack_lines.iter().rev().skip(1) {
-        line.char_indices().for_each(|(i, c)| {
-            if i % 4 == 1 {
-                let stack_index = (i - 1) / 4;
-                if c.is_alphabetic() {
-                    orig_stacks[stack_index].push(c);
-                }
+    let commands: Vec<(usize, Command)> = command_lines
+        .iter()
+        .map(|&(line_number, line)| match line.parse() {
+            Ok(cmd) => (line_number, cmd),
+            Err(err) => {
+                eprintln!("line {}: {}", line_number, err);
+                process::exit(1);
             }
         })
-    }
-
-    let commands: Vec<Command> = command_lines
-        .iter()
-        .map(|line| line.parse().unwrap())
         .collect();
 
     // println!("Start stack state:\n{:?}", orig_stacks);
 
-    {
-        let mut stacks = orig_stacks.clone();
+    if let Some(target_filename) = parse_option("--plan") {
+        let target_input = fs::read_to_string(target_filename).unwrap();
+        let target_lines: Vec<&str> = target_input
+            .split('\n')
+            .take_while(|l| !l.is_empty())
+            .collect();
+        let target_stacks = parse_drawing(&target_lines);
+
+        let crane = match parse_capacity() {
+            Some(capacity) => Crane::Capacity(capacity),
+            None => match parse_option("--crane").as_deref() {
+                None | Some("9000") => Crane::CrateMover9000,
+                Some("9001") => Crane::CrateMover9001,
+                Some(other) => {
+                    eprintln!("unknown crane model: {}", other);
+                    process::exit(1);
+                }
+            },
+        };
+
+        let limit = match parse_option("--plan-limit") {
+            Some(n) => n.parse().unwrap_or_else(|_| {
+                eprintln!("--plan-limit expects a number");
+                process::exit(1);
+            }),
+            None => 1_000_000,
+        };
 
-        for cmd in &commands {
-            for _ in 1..=cmd.move_count {
-                let val = stacks[cmd.source].pop().unwrap();
-                stacks[cmd.destination].push(val);
+        match plan_commands(crane, &orig_stacks, &target_stacks, limit) {
+            Ok(plan) => {
+                println!("{}\n", render_drawing(&orig_stacks));
+                for cmd in plan {
+                    println!("{}", cmd);
+                }
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
             }
         }
+        return;
+    }
 
-        let result: String = stacks.iter().map(|s| s.last().unwrap()).collect();
-
-        println!("Part 1:");
-        println!("{}", result);
+    let mut cranes = vec![
+        ("Part 1", Crane::CrateMover9000),
+        ("Part 2", Crane::CrateMover9001),
+    ];
+    if let Some(capacity) = parse_capacity() {
+        cranes.push(("Capacity", Crane::Capacity(capacity)));
     }
 
-    {
+    let replay = env::args().any(|a| a == "--replay");
+
+    for (title, crane) in cranes {
         let mut stacks = orig_stacks.clone();
 
-        for cmd in &commands {
-            let mut buff: Vec<char> = vec![];
-            for _ in 1..=cmd.move_count {
-                buff.push(stacks[cmd.source].pop().unwrap());
+        println!("{}:", title);
+        if replay {
+            println!("{}\n", render_drawing(&stacks));
+            for (line, cmd) in &commands {
+                if let Err(err) = crane.apply(&mut stacks, *line, cmd) {
+                    println!("error: {}", err);
+                    break;
+                }
+                println!("{}\n{}\n", cmd, render_drawing(&stacks));
             }
-            stacks[cmd.destination].extend(buff.iter().rev());
+            continue;
         }
 
-        let result: String = stacks.iter().map(|s| s.last().unwrap()).collect();
-
-        println!("Part 2:");
-        println!("{}", result);
+        match crane.run(&mut stacks, &commands) {
+            Ok(()) => println!("{}", top_crates(&stacks)),
+            Err(err) => println!("error: {}", err),
+        }
     }
 }
@@ -1,18 +1,262 @@
+use std::collections::VecDeque;
+use std::env;
 use std::fs;
+use std::io::{self, BufReader, Read};
+use std::ops::Range;
 
-fn has_only_unique_chars(s: &str) -> bool {
-    s.char_indices()
-        .all(|(i1, c1)| s.char_indices().all(|(i2, c2)| c2 != c1 || i2 == i1))
+/// Tracks the last `window_len` bytes of a stream and how many of them repeat
+struct MarkerDetector {
+    window_len: usize,
+    window: VecDeque<u8>,
+    counts: [usize; 256],
+    repeated: usize,
 }
 
-fn index_of_unique_char_sequence(transmission: &str, search_len: usize) -> Result<usize, String> {
-    let res = (0..transmission.len() - 4).find(|i| {
-        let seq: String = transmission.chars().skip(*i).take(search_len).collect();
-        has_only_unique_chars(&seq)
-    });
+impl MarkerDetector {
+    fn new(window_len: usize) -> MarkerDetector {
+        assert!(window_len > 0, "Window must not be empty");
+        MarkerDetector {
+            window_len,
+            window: VecDeque::with_capacity(window_len + 1),
+            counts: [0; 256],
+            repeated: 0,
+        }
+    }
+
+    /// Returns whether the last `window_len` bytes, including `byte`, are all different
+    fn push(&mut self, byte: u8) -> bool {
+        self.window.push_back(byte);
+        self.counts[byte as usize] += 1;
+        if self.counts[byte as usize] == 2 {
+            self.repeated += 1;
+        }
+
+        if self.window.len() > self.window_len {
+            let old = self.window.pop_front().unwrap();
+            self.counts[old as usize] -= 1;
+            if self.counts[old as usize] == 1 {
+                self.repeated -= 1;
+            }
+        }
+
+        self.window.len() == self.window_len && self.repeated == 0
+    }
+}
+
+/// Returns the number of bytes read up to the end of every marker found in the stream
+fn find_markers<R: Read>(reader: R, window_len: usize) -> io::Result<Vec<usize>> {
+    let mut detector = MarkerDetector::new(window_len);
+    let mut markers = vec![];
+
+    for (i, byte) in BufReader::new(reader).bytes().enumerate() {
+        if detector.push(byte?) {
+            markers.push(i + 1);
+        }
+    }
+
+    Ok(markers)
+}
+
+/// Returns the number of bytes up to the end of the first marker in `data`
+fn first_marker_end(data: &[u8], window_len: usize) -> Option<usize> {
+    let mut detector = MarkerDetector::new(window_len);
+    data.iter().position(|&b| detector.push(b)).map(|i| i + 1)
+}
+
+const PACKET_MARKER_LEN: usize = 4;
+const MESSAGE_MARKER_LEN: usize = 14;
+
+/// Part of the datastream, given as a range of byte offsets.
+///
+/// A stream holds a single packet: after its start-of-packet marker, any later four
+/// different bytes are ordinary data. Every start-of-message marker which does not overlap
+/// the previous one starts a new message, and its payload runs up to the next such marker
+/// or the end of the stream.
+#[derive(Debug, Clone, PartialEq)]
+enum Frame {
+    /// Bytes before the start-of-packet marker
+    Preamble(Range<usize>),
+    StartOfPacket(Range<usize>),
+    /// Bytes between the start-of-packet marker and the first start-of-message marker
+    Header(Range<usize>),
+    /// May overlap the start-of-packet marker, as the first message marker is searched for
+    /// from the start of the packet
+    StartOfMessage(Range<usize>),
+    /// Bytes after a start-of-message marker up to the next one
+    Payload(Range<usize>),
+    /// Bytes after the start-of-packet marker which never got a start-of-message marker
+    CorruptedTail(Range<usize>),
+}
+
+impl Frame {
+    fn range(&self) -> &Range<usize> {
+        match self {
+            Frame::Preamble(r)
+            | Frame::StartOfPacket(r)
+            | Frame::Header(r)
+            | Frame::StartOfMessage(r)
+            | Frame::Payload(r)
+            | Frame::CorruptedTail(r) => r,
+        }
+    }
+}
+
+enum DecoderState {
+    SeekPacket,
+    /// Looking for the first message marker of the packet starting at `packet_start`
+    SeekMessage {
+        packet_start: usize,
+    },
+    Payload,
+    Done,
+}
+
+/// Splits a datastream into frames
+struct FrameDecoder<'a> {
+    data: &'a [u8],
+    cursor: usize,
+    state: DecoderState,
+    pending: Option<Frame>,
+}
+
+impl<'a> FrameDecoder<'a> {
+    fn new(data: &'a [u8]) -> FrameDecoder<'a> {
+        FrameDecoder {
+            data,
+            cursor: 0,
+            state: DecoderState::SeekPacket,
+            pending: None,
+        }
+    }
 
-    if let Some(idx) = res {
-        Ok(idx)
+    /// Queues the marker frame and returns the frame of the bytes before it, unless there
+    /// are none
+    fn split_at_marker(
+        &mut self,
+        lead_frame: fn(Range<usize>) -> Frame,
+        marker_frame: fn(Range<usize>) -> Frame,
+        marker: Range<usize>,
+    ) -> Option<Frame> {
+        let start = self.cursor;
+        self.cursor = marker.end;
+        self.pending = Some(marker_frame(marker.clone()));
+
+        if marker.start > start {
+            Some(lead_frame(start..marker.start))
+        } else {
+            self.pending.take()
+        }
+    }
+}
+
+impl<'a> Iterator for FrameDecoder<'a> {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        if let Some(frame) = self.pending.take() {
+            return Some(frame);
+        }
+
+        let start = self.cursor;
+        let rest = &self.data[start..];
+
+        match self.state {
+            DecoderState::SeekPacket => match first_marker_end(rest, PACKET_MARKER_LEN) {
+                Some(end) => {
+                    let marker = start + end - PACKET_MARKER_LEN..start + end;
+                    self.state = DecoderState::SeekMessage {
+                        packet_start: marker.start,
+                    };
+                    self.split_at_marker(Frame::Preamble, Frame::StartOfPacket, marker)
+                }
+                None => {
+                    self.cursor = self.data.len();
+                    self.state = DecoderState::Done;
+
+                    if rest.is_empty() {
+                        None
+                    } else {
+                        Some(Frame::Preamble(start..self.cursor))
+                    }
+                }
+            },
+            DecoderState::SeekMessage { packet_start } => {
+                match first_marker_end(&self.data[packet_start..], MESSAGE_MARKER_LEN) {
+                    Some(end) => {
+                        let marker = packet_start + end - MESSAGE_MARKER_LEN..packet_start + end;
+                        self.state = DecoderState::Payload;
+                        self.split_at_marker(Frame::Header, Frame::StartOfMessage, marker)
+                    }
+                    None => {
+                        self.cursor = self.data.len();
+                        self.state = DecoderState::Done;
+
+                        if rest.is_empty() {
+                            None
+                        } else {
+                            Some(Frame::CorruptedTail(start..self.cursor))
+                        }
+                    }
+                }
+            }
+            DecoderState::Payload => match first_marker_end(rest, MESSAGE_MARKER_LEN) {
+                Some(end) => {
+                    let marker = start + end - MESSAGE_MARKER_LEN..start + end;
+                    self.split_at_marker(Frame::Payload, Frame::StartOfMessage, marker)
+                }
+                None => {
+                    self.cursor = self.data.len();
+                    self.state = DecoderState::Done;
+
+                    if rest.is_empty() {
+                        None
+                    } else {
+                        Some(Frame::Payload(start..self.cursor))
+                    }
+                }
+            },
+            DecoderState::Done => None,
+        }
+    }
+}
+
+fn print_frame_statistics(frames: &[Frame]) {
+    let count = |pred: fn(&Frame) -> bool| frames.iter().filter(|f| pred(f)).count();
+    let payload_lengths: Vec<usize> = frames
+        .iter()
+        .filter_map(|f| match f {
+            Frame::Payload(r) => Some(r.len()),
+            _ => None,
+        })
+        .collect();
+
+    println!(
+        "packets: {}",
+        count(|f| matches!(f, Frame::StartOfPacket(_)))
+    );
+    println!(
+        "messages: {}",
+        count(|f| matches!(f, Frame::StartOfMessage(_)))
+    );
+    if !payload_lengths.is_empty() {
+        println!(
+            "payload length: min {}, max {}, total {}",
+            payload_lengths.iter().min().unwrap(),
+            payload_lengths.iter().max().unwrap(),
+            payload_lengths.iter().sum::<usize>()
+        );
+    }
+    match frames.iter().find(|f| matches!(f, Frame::CorruptedTail(_))) {
+        Some(tail) => println!("corrupted tail: {} bytes", tail.range().len()),
+        None => println!("corrupted tail: none"),
+    }
+}
+
+fn end_of_first_marker(transmission: &str, window_len: usize) -> Result<usize, String> {
+    let markers = find_markers(transmission.as_bytes(), window_len).map_err(|e| e.to_string())?;
+
+    if let Some(&end) = markers.first() {
+        Ok(end)
     } else {
         Err("Could not find unique sequence".to_owned())
     }
@@ -24,15 +268,52 @@ fn main() {
     let input = fs::read_to_string(filename).unwrap();
     let transmission = input.trim();
 
-    let start_of_packet_idx = index_of_unique_char_sequence(&transmission, 4).unwrap();
-    let chars_that_were_checked_to_find_sop = start_of_packet_idx + 4;
+    let chars_that_were_checked_to_find_sop = end_of_first_marker(transmission, 4).unwrap();
 
     println!("Part 1:");
     println!("{}", chars_that_were_checked_to_find_sop);
 
-    let start_of_message_idx = index_of_unique_char_sequence(&transmission, 14).unwrap();
-    let chars_that_were_checked_to_find_som = start_of_message_idx + 14;
+    let chars_that_were_checked_to_find_som = end_of_first_marker(transmission, 14).unwrap();
 
     println!("Part 2:");
     println!("{}", chars_that_were_checked_to_find_som);
+
+    let args: Vec<String> = env::args().collect();
+    if let Some(i) = args.iter().position(|a| a == "--window") {
+        let window_len: usize = args
+            .get(i + 1)
+            .and_then(|n| n.parse().ok())
+            .filter(|&n| n > 0)
+            .expect("--window expects a positive number");
+        let markers = find_markers(transmission.as_bytes(), window_len).unwrap();
+        let positions: Vec<String> = markers.iter().map(|m| m.to_string()).collect();
+
+        println!("Markers of length {}:", window_len);
+        println!("{}", positions.join(" "));
+    }
+
+    if args.iter().any(|a| a == "--frames") {
+        let frames: Vec<Frame> = FrameDecoder::new(transmission.as_bytes()).collect();
+
+        println!("Frames:");
+        for frame in &frames {
+            let range = frame.range();
+            let kind = match frame {
+                Frame::Preamble(_) => "preamble",
+                Frame::StartOfPacket(_) => "start-of-packet",
+                Frame::Header(_) => "header",
+                Frame::StartOfMessage(_) => "start-of-message",
+                Frame::Payload(_) => "payload",
+                Frame::CorruptedTail(_) => "corrupted tail",
+            };
+            println!(
+                "{}..{} {}: {}",
+                range.start,
+                range.end,
+                kind,
+                &transmission[range.clone()]
+            );
+        }
+        print_frame_statistics(&frames);
+    }
 }
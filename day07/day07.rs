@@ -1,8 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::env;
 use std::fmt::Display;
 use std::fs;
+use std::io::{self, BufRead, Write};
 use std::iter::Iterator;
 use std::iter::Peekable;
+use std::path::Path;
+use std::process;
 
 #[derive(Debug, PartialEq)]
 enum Node {
@@ -14,81 +18,167 @@ fn is_command_line(s: &str) -> bool {
     s.starts_with("$")
 }
 
+#[derive(Debug)]
+enum DiagnosticKind {
+    UnknownCommand(String),
+    OutputWithoutLs,
+    MalformedOutput,
+    InvalidSize(String),
+    CdIntoFile(String),
+    CdAboveRoot,
+    SizeConflict {
+        name: String,
+        old: usize,
+        new: usize,
+    },
+    TypeConflict(String),
+}
+
+/// Inconsistency found in a transcript, with 1-based line number
+#[derive(Debug)]
+struct Diagnostic {
+    line: usize,
+    kind: DiagnosticKind,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            DiagnosticKind::UnknownCommand(cmd) => write!(f, "unknown command: {}", cmd),
+            DiagnosticKind::OutputWithoutLs => write!(f, "output line outside of ls"),
+            DiagnosticKind::MalformedOutput => write!(f, "expected `dir NAME` or `SIZE NAME`"),
+            DiagnosticKind::InvalidSize(size) => write!(f, "failed to parse size: {}", size),
+            DiagnosticKind::CdIntoFile(name) => write!(f, "cd into file: {}", name),
+            DiagnosticKind::CdAboveRoot => write!(f, "cd .. at root"),
+            DiagnosticKind::SizeConflict { name, old, new } => {
+                write!(f, "{} listed with size {}, previously {}", name, new, old)
+            }
+            DiagnosticKind::TypeConflict(name) => {
+                write!(f, "{} listed both as a file and a directory", name)
+            }
+        }
+    }
+}
+
 impl Node {
     fn from_command_lines<I>(it: &mut Peekable<I>) -> Result<Self, String>
     where
         I: Iterator<Item = String>,
     {
-        let mut root_dir = Self::new_dir();
+        let (root_dir, diagnostics) = Self::parse_command_lines(it);
+
+        if diagnostics.is_empty() {
+            Ok(root_dir)
+        } else {
+            let messages: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+            Err(messages.join("\n"))
+        }
+    }
 
+    /// Builds the tree from a transcript, collecting every inconsistency instead of
+    /// stopping at the first one. Inconsistent lines are skipped, and the first listing
+    /// of an entry wins.
+    fn parse_command_lines<I>(it: I) -> (Self, Vec<Diagnostic>)
+    where
+        I: Iterator<Item = String>,
+    {
+        let mut root_dir = Self::new_dir();
         let mut path: Vec<String> = vec![];
+        let mut diagnostics = vec![];
 
-        let mut current_dir = &mut root_dir;
+        let mut in_ls = false;
+        let mut in_unknown_command = false;
 
-        while let Some(cmd) = it.next() {
-            assert!(is_command_line(&cmd));
+        for (i, line) in it.enumerate() {
+            let mut report = |kind| diagnostics.push(Diagnostic { line: i + 1, kind });
 
-            match cmd.split(' ').skip(1).collect::<Vec<&str>>()[..] {
-                ["cd", "/"] => {
-                    path.clear();
-                    current_dir = &mut root_dir;
+            if line.is_empty() {
+                continue;
+            }
+
+            let current_dir = root_dir
+                .find_path_mut(&path)
+                .and_then(|node| node.entries_mut())
+                .unwrap();
+
+            if is_command_line(&line) {
+                in_ls = false;
+                in_unknown_command = false;
+
+                match line.split(' ').skip(1).collect::<Vec<&str>>()[..] {
+                    ["cd", "/"] => path.clear(),
+                    ["cd", ".."] => {
+                        if path.pop().is_none() {
+                            report(DiagnosticKind::CdAboveRoot);
+                        }
+                    }
+                    ["cd", dir] => match current_dir.get(dir) {
+                        Some(Self::File(_)) => report(DiagnosticKind::CdIntoFile(dir.to_owned())),
+                        Some(Self::Dir(_)) => path.push(dir.to_owned()),
+                        None => {
+                            current_dir.insert(dir.to_owned(), Self::new_dir());
+                            path.push(dir.to_owned());
+                        }
+                    },
+                    ["ls"] => in_ls = true,
+                    _ => {
+                        report(DiagnosticKind::UnknownCommand(line.clone()));
+                        in_unknown_command = true;
+                    }
                 }
-                ["cd", ".."] => {
-                    path.pop().unwrap();
-                    current_dir = root_dir.find_path_mut(&path.as_slice()).unwrap();
+                continue;
+            }
+
+            if !in_ls {
+                if !in_unknown_command {
+                    report(DiagnosticKind::OutputWithoutLs);
                 }
-                ["cd", dir] => {
-                    match current_dir.entries().unwrap().get(dir) {
-                        Some(_) => {}
-                        None => {
-                            current_dir
-                                .entries_mut()
-                                .unwrap()
-                                .insert(dir.to_owned(), Self::new_dir());
+                continue;
+            }
+
+            let (size_or_dir, name) = match line.split_once(' ') {
+                Some(parts) => parts,
+                None => {
+                    report(DiagnosticKind::MalformedOutput);
+                    continue;
+                }
+            };
+
+            match (size_or_dir, current_dir.get(name)) {
+                ("dir", None) => {
+                    current_dir.insert(name.to_owned(), Self::new_dir());
+                }
+                ("dir", Some(Self::Dir(_))) => {}
+                ("dir", Some(Self::File(_))) => {
+                    report(DiagnosticKind::TypeConflict(name.to_owned()))
+                }
+                (size_str, existing) => {
+                    let size = match size_str.parse::<usize>() {
+                        Ok(size) => size,
+                        Err(_) => {
+                            report(DiagnosticKind::InvalidSize(size_str.to_owned()));
+                            continue;
                         }
                     };
-                    path.push(dir.to_owned());
-                    current_dir = current_dir.entries_mut().unwrap().get_mut(dir).unwrap();
-                }
-                ["ls"] => {
-                    while it.peek().is_some() && !is_command_line(it.peek().unwrap()) {
-                        let line = it.next().unwrap();
-                        let (size_or_dir, name) = line.split_once(' ').unwrap();
-
-                        match size_or_dir {
-                            "dir" => {
-                                if let None = current_dir.entries_mut().unwrap().get(name) {
-                                    current_dir
-                                        .entries_mut()
-                                        .unwrap()
-                                        .insert(name.to_owned(), Self::new_dir());
-                                }
-                            }
-                            size_str => {
-                                if let Ok(size) = size_str.parse::<usize>() {
-                                    let foo = current_dir
-                                        .entries_mut()
-                                        .unwrap()
-                                        .insert(name.to_owned(), Self::File(size));
-
-                                    if let Some(f) = foo {
-                                        println!("{} {:?} {:?}", name, f, Self::File(size));
-                                        assert!(f == Self::File(size));
-                                    }
-                                } else {
-                                    return Err(format!("failed to parse size: {}", size_str));
-                                }
-                            }
+
+                    match existing {
+                        None => {
+                            current_dir.insert(name.to_owned(), Self::File(size));
                         }
+                        Some(Self::File(old)) if *old == size => {}
+                        Some(Self::File(old)) => report(DiagnosticKind::SizeConflict {
+                            name: name.to_owned(),
+                            old: *old,
+                            new: size,
+                        }),
+                        Some(Self::Dir(_)) => report(DiagnosticKind::TypeConflict(name.to_owned())),
                     }
                 }
-                _ => {
-                    return Err(format!("unknown command {}", cmd).to_owned());
-                }
             }
         }
 
-        Ok(root_dir)
+        (root_dir, diagnostics)
     }
 
     fn new_dir() -> Self {
@@ -109,6 +199,14 @@ impl Node {
         }
     }
 
+    fn find_path(&self, path: &[String]) -> Option<&Node> {
+        if path.is_empty() {
+            Some(self)
+        } else {
+            self.entries()?.get(&path[0])?.find_path(&path[1..])
+        }
+    }
+
     fn find_path_mut(&mut self, path: &[String]) -> Option<&mut Node> {
         if path.is_empty() {
             Some(self)
@@ -123,6 +221,57 @@ impl Node {
         }
     }
 
+    fn sorted_entries(&self) -> Vec<(&String, &Node)> {
+        let mut entries: Vec<(&String, &Node)> = self.entries().into_iter().flatten().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        entries
+    }
+
+    /// Files become sizes and directories become objects keyed by entry names
+    fn to_json(&self) -> String {
+        fn write(out: &mut String, node: &Node, depth: usize) {
+            match node {
+                Node::File(size) => out.push_str(&size.to_string()),
+                Node::Dir(_) => {
+                    let entries = node.sorted_entries();
+                    if entries.is_empty() {
+                        out.push_str("{}");
+                        return;
+                    }
+                    out.push_str("{\n");
+                    for (i, (name, child)) in entries.iter().enumerate() {
+                        out.push_str(&"  ".repeat(depth + 1));
+                        write_json_string(out, name);
+                        out.push_str(": ");
+                        write(out, child, depth + 1);
+                        if i + 1 < entries.len() {
+                            out.push(',');
+                        }
+                        out.push('\n');
+                    }
+                    out.push_str(&"  ".repeat(depth));
+                    out.push('}');
+                }
+            }
+        }
+
+        let mut out = String::new();
+        write(&mut out, self, 0);
+        out
+    }
+
+    fn from_json(s: &str) -> Result<Self, String> {
+        let mut parser = JsonParser {
+            chars: s.char_indices().peekable(),
+        };
+        let node = parser.parse_node()?;
+        parser.skip_whitespace();
+        match parser.chars.next() {
+            Some((i, c)) => Err(format!("at {}: unexpected '{}' after the tree", i, c)),
+            None => Ok(node),
+        }
+    }
+
     fn size(&self) -> usize {
         match self {
             Self::File(size) => *size,
@@ -137,39 +286,440 @@ impl Node {
         }
     }
 
-    fn sum_all_directory_sizes_below(&self, max_size: usize) -> usize {
-        match self {
-            Self::File(_) => 0,
-            Self::Dir(entries) => {
-                Some(self.size()).filter(|s| *s <= max_size).unwrap_or(0)
-                    + entries
-                        .iter()
-                        .map(|(_, node)| node.sum_all_directory_sizes_below(max_size))
-                        .sum::<usize>()
+    /// Computes the size of this node and everything inside it in a single post-order pass
+    fn sized(&self) -> SizedNode<'_> {
+        let entries: Vec<(&String, SizedNode)> = self
+            .sorted_entries()
+            .into_iter()
+            .map(|(name, entry)| (name, entry.sized()))
+            .collect();
+        let size = match self {
+            Self::File(size) => *size,
+            Self::Dir(_) => entries.iter().map(|(_, entry)| entry.size).sum(),
+        };
+        SizedNode {
+            node: self,
+            size,
+            entries,
+        }
+    }
+
+    /// Lists every directory with its size, subdirectories before their parents
+    fn directories(&self) -> impl Iterator<Item = (String, usize)> {
+        fn visit(node: &SizedNode, path: &mut Vec<String>, out: &mut Vec<(String, usize)>) {
+            if !node.node.is_dir() {
+                return;
             }
+            for (name, entry) in &node.entries {
+                path.push((*name).clone());
+                visit(entry, path, out);
+                path.pop();
+            }
+            out.push((format_path(path), node.size));
         }
+
+        let mut out = vec![];
+        visit(&self.sized(), &mut vec![], &mut out);
+        out.into_iter()
+    }
+
+    fn sum_all_directory_sizes_below(&self, max_size: usize) -> usize {
+        self.directories()
+            .map(|(_, size)| size)
+            .filter(|size| *size <= max_size)
+            .sum()
     }
 
     fn find_directory_by_size_lowerbound(&self, lowerbound: usize) -> usize {
-        match self {
-            Self::File(_) => usize::MAX,
-            Self::Dir(entries) => *Some(self.size())
+        self.directories()
+            .map(|(_, size)| size)
+            .filter(|size| *size >= lowerbound)
+            .min()
+            .unwrap_or(usize::MAX)
+    }
+
+    /// Selects directories, none inside another one, whose total size is the smallest one
+    /// not below `space_to_free`. Protected directories, their parents and everything
+    /// inside them are never selected.
+    ///
+    /// Walks the directories in depth-first order, where selecting a directory skips
+    /// over its subdirectories, and keeps the totals reachable at every step as a bit
+    /// set. Totals above the best single directory are dropped.
+    fn plan_cleanup(
+        &self,
+        space_to_free: usize,
+        protected: &[Vec<String>],
+    ) -> Option<Vec<(String, usize)>> {
+        let mut directories = vec![];
+        let size = self.cleanup_candidates(&mut vec![], protected, &mut directories);
+        let upper_bound = directories
+            .iter()
+            .filter(|d| d.selectable && d.size >= space_to_free)
+            .map(|d| d.size)
+            .min()
+            .unwrap_or(size);
+
+        let planner = CleanupPlanner {
+            directories,
+            upper_bound,
+        };
+        let end = planner.directories.len();
+        let reached = planner.totals(end, |p| p == end).swap_remove(end)?;
+        let total = reached.first_from(space_to_free)?;
+        Some(planner.chosen(total))
+    }
+
+    /// Lists the directories in depth-first order, leaving out everything inside
+    /// protected paths, and returns the size of this one
+    fn cleanup_candidates(
+        &self,
+        path: &mut Vec<String>,
+        protected: &[Vec<String>],
+        candidates: &mut Vec<CleanupCandidate>,
+    ) -> usize {
+        if protected.iter().any(|p| path.starts_with(p)) {
+            return self.size();
+        }
+
+        let position = candidates.len();
+        candidates.push(CleanupCandidate {
+            path: format_path(path),
+            size: 0,
+            end: 0,
+            selectable: !is_protected(path, protected),
+        });
+
+        let mut size = 0;
+        for (name, entry) in self.sorted_entries() {
+            if let Node::File(file_size) = entry {
+                size += file_size;
+                continue;
+            }
+            path.push(name.clone());
+            size += entry.cleanup_candidates(path, protected, candidates);
+            path.pop();
+        }
+
+        candidates[position].size = size;
+        candidates[position].end = candidates.len();
+        size
+    }
+
+    /// Reads a directory tree from disk, using file lengths as sizes. Symlinks, entries
+    /// whose names cannot appear in a transcript and entries that cannot be read are
+    /// skipped and returned separately.
+    fn from_disk(dir: &Path) -> io::Result<(Self, Vec<String>)> {
+        let mut entries = HashMap::new();
+        let mut skipped = vec![];
+
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry
+                .file_name()
+                .into_string()
+                .ok()
+                .filter(|name| !name.contains(' ') && !name.contains('\n'));
+            let node = match (&name, entry.file_type()) {
+                (Some(_), Ok(file_type)) if file_type.is_dir() => Self::from_disk(&entry.path())
+                    .ok()
+                    .map(|(node, mut inner_skipped)| {
+                        skipped.append(&mut inner_skipped);
+                        node
+                    }),
+                (Some(_), Ok(file_type)) if !file_type.is_symlink() => entry
+                    .metadata()
+                    .ok()
+                    .map(|metadata| Self::File(metadata.len() as usize)),
+                _ => None,
+            };
+
+            match (name, node) {
+                (Some(name), Some(node)) => {
+                    entries.insert(name, node);
+                }
+                _ => skipped.push(entry.path().display().to_string()),
+            }
+        }
+
+        Ok((Self::Dir(entries), skipped))
+    }
+
+    fn ls_output(&self) -> Vec<String> {
+        self.sorted_entries()
+            .into_iter()
+            .map(|(name, entry)| match entry {
+                Self::File(size) => format!("{} {}", size, name),
+                Self::Dir(_) => format!("dir {}", name),
+            })
+            .collect()
+    }
+
+    /// Produces a `cd`/`ls` transcript visiting every directory, either depth first
+    /// moving with `cd ..` or breadth first starting each visit from `cd /`.
+    /// With `redundant_ls` every directory is listed twice.
+    fn to_transcript(&self, breadth_first: bool, redundant_ls: bool) -> Vec<String> {
+        fn ls(out: &mut Vec<String>, node: &Node) {
+            out.push("$ ls".to_owned());
+            out.extend(node.ls_output());
+        }
+
+        fn depth_first(out: &mut Vec<String>, node: &Node, redundant_ls: bool) {
+            ls(out, node);
+            for (name, entry) in node.sorted_entries() {
+                if entry.is_dir() {
+                    out.push(format!("$ cd {}", name));
+                    depth_first(out, entry, redundant_ls);
+                    out.push("$ cd ..".to_owned());
+                    if redundant_ls {
+                        ls(out, node);
+                    }
+                }
+            }
+        }
+
+        let mut out = vec!["$ cd /".to_owned()];
+
+        if breadth_first {
+            let mut queue: VecDeque<(Vec<&String>, &Node)> = VecDeque::new();
+            queue.push_back((vec![], self));
+
+            while let Some((path, node)) = queue.pop_front() {
+                if !path.is_empty() {
+                    out.push("$ cd /".to_owned());
+                }
+                for name in &path {
+                    out.push(format!("$ cd {}", name));
+                }
+                ls(&mut out, node);
+                if redundant_ls {
+                    ls(&mut out, node);
+                }
+                for (name, entry) in node.sorted_entries() {
+                    if entry.is_dir() {
+                        let mut entry_path = path.clone();
+                        entry_path.push(name);
+                        queue.push_back((entry_path, entry));
+                    }
+                }
+            }
+        } else {
+            depth_first(&mut out, self, redundant_ls);
+        }
+
+        out
+    }
+}
+
+/// Whether `path` is protected, is inside a protected path or contains one
+fn is_protected(path: &[String], protected: &[Vec<String>]) -> bool {
+    protected
+        .iter()
+        .any(|p| p.starts_with(path) || path.starts_with(p))
+}
+
+/// Directory considered by the cleanup planner
+struct CleanupCandidate {
+    path: String,
+    size: usize,
+    /// Position right after the last subdirectory
+    end: usize,
+    selectable: bool,
+}
+
+/// Set of totals up to `bound`
+#[derive(Clone)]
+struct Totals {
+    bound: usize,
+    bits: Vec<u64>,
+    /// Words past it are all zero
+    used: usize,
+}
+
+impl Totals {
+    fn new(bound: usize) -> Self {
+        Self {
+            bound,
+            bits: vec![0; bound / 64 + 1],
+            used: 0,
+        }
+    }
+
+    fn insert(&mut self, total: usize) {
+        if total <= self.bound {
+            self.bits[total / 64] |= 1 << (total % 64);
+            self.used = self.used.max(total / 64 + 1);
+        }
+    }
+
+    fn contains(&self, total: usize) -> bool {
+        total <= self.bound && self.bits[total / 64] & (1 << (total % 64)) != 0
+    }
+
+    fn first_from(&self, total: usize) -> Option<usize> {
+        (total..=self.bound).find(|&t| self.contains(t))
+    }
+
+    fn union(&mut self, other: &Totals) {
+        for (bits, other) in self.bits.iter_mut().zip(&other.bits[..other.used]) {
+            *bits |= other;
+        }
+        self.used = self.used.max(other.used);
+    }
+
+    /// Adds every total of `other` increased by `shift`
+    fn union_shifted(&mut self, other: &Totals, shift: usize) {
+        let (words, offset) = (shift / 64, shift % 64);
+        if words >= self.bits.len() {
+            return;
+        }
+        let used = other.used.min(self.bits.len() - words);
+        let mut carry = 0;
+        for (bits, other) in self.bits[words..words + used]
+            .iter_mut()
+            .zip(&other.bits[..used])
+        {
+            *bits |= other << offset | carry;
+            carry = if offset == 0 {
+                0
+            } else {
+                other >> (64 - offset)
+            };
+        }
+        if let Some(bits) = self.bits.get_mut(words + used) {
+            *bits |= carry;
+        }
+        self.used = self.used.max((words + used + 1).min(self.bits.len()));
+        let last = self.bits.len() - 1;
+        self.bits[last] &= u64::MAX >> (63 - self.bound % 64);
+    }
+}
+
+struct CleanupPlanner {
+    directories: Vec<CleanupCandidate>,
+    /// No total above it is better than the best single directory
+    upper_bound: usize,
+}
+
+impl CleanupPlanner {
+    /// Bytes of totals kept at once while reconstructing the plan
+    const MEMORY: usize = 1 << 26;
+
+    /// Walks the directories up to position `stop`, returning the totals reachable at
+    /// each position for which `keep` holds
+    fn totals(&self, stop: usize, keep: impl Fn(usize) -> bool) -> Vec<Option<Totals>> {
+        let count = self.directories.len();
+        let mut pending: Vec<Option<Totals>> = (0..=count).map(|_| None).collect();
+        let mut kept: Vec<Option<Totals>> = (0..=count).map(|_| None).collect();
+
+        let mut start = Totals::new(self.upper_bound);
+        start.insert(0);
+        pending[0] = Some(start);
+
+        for position in 0..stop {
+            let totals = pending[position]
+                .take()
+                .expect("every position is reachable");
+            let directory = &self.directories[position];
+            if directory.selectable {
+                pending[directory.end]
+                    .get_or_insert_with(|| Totals::new(self.upper_bound))
+                    .union_shifted(&totals, directory.size);
+            }
+            if keep(position) {
+                kept[position] = Some(totals.clone());
+            }
+            match &mut pending[position + 1] {
+                Some(next) => next.union(&totals),
+                next => *next = Some(totals),
+            }
+        }
+        if keep(stop) {
+            kept[stop] = pending[stop].take();
+        }
+        kept
+    }
+
+    /// Walks back from the end towards the start, preferring to select the outermost
+    /// directory. Totals are recomputed for a window of positions at a time.
+    fn chosen(&self, mut total: usize) -> Vec<(String, usize)> {
+        let window = (Self::MEMORY / (self.upper_bound / 8 + 1)).max(1);
+        let mut chosen = vec![];
+        let mut known: Vec<Option<Totals>> = vec![];
+        let mut position = self.directories.len();
+
+        while position > 0 {
+            if known.get(position - 1).is_none_or(|t| t.is_none()) {
+                let low = position.saturating_sub(window);
+                let end = position;
+                known = self.totals(position - 1, |p| {
+                    (low..end).contains(&p) || (low + 1..=end).contains(&self.directories[p].end)
+                });
+            }
+
+            let selected = self.directories[..position]
                 .iter()
-                .filter(|s| (**s) >= lowerbound)
-                .chain(
-                    entries
-                        .iter()
-                        .map(|(_, node)| node.find_directory_by_size_lowerbound(lowerbound))
-                        .collect::<Vec<usize>>()
-                        .iter(),
-                )
-                .min()
-                .unwrap_or(&usize::MAX),
+                .enumerate()
+                .find(|(p, d)| {
+                    d.end == position
+                        && d.selectable
+                        && d.size != 0
+                        && d.size <= total
+                        && known[*p]
+                            .as_ref()
+                            .is_some_and(|t| t.contains(total - d.size))
+                });
+            match selected {
+                Some((p, directory)) => {
+                    chosen.push((directory.path.clone(), directory.size));
+                    total -= directory.size;
+                    position = p;
+                }
+                None => position -= 1,
+            }
         }
+        chosen.reverse();
+        chosen
     }
 }
 
-impl Display for Node {
+fn human_readable_size(size: usize) -> String {
+    let units = ["K", "M", "G", "T"];
+    if size < 1024 {
+        return format!("{}B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < units.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, units[unit])
+}
+
+/// Node together with its size and its entries sorted by name, valid as long as the tree
+/// is borrowed
+struct SizedNode<'a> {
+    node: &'a Node,
+    size: usize,
+    entries: Vec<(&'a String, SizedNode<'a>)>,
+}
+
+/// Tree rendering of a node, with entries sorted by name
+struct Tree<'a> {
+    root: SizedNode<'a>,
+    human_readable: bool,
+}
+
+impl<'a> Tree<'a> {
+    fn new(node: &'a Node, human_readable: bool) -> Tree<'a> {
+        Tree {
+            root: node.sized(),
+            human_readable,
+        }
+    }
+}
+
+impl<'a> Display for Tree<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
         fn write_indent(
             f: &mut std::fmt::Formatter<'_>,
@@ -183,46 +733,557 @@ impl Display for Node {
 
         fn disp(
             f: &mut std::fmt::Formatter<'_>,
-            node: &Node,
+            tree: &Tree,
+            node: &SizedNode,
             name: &str,
             depth: usize,
         ) -> Result<(), std::fmt::Error> {
-            match node {
+            let size = |size: usize| {
+                if tree.human_readable {
+                    human_readable_size(size)
+                } else {
+                    size.to_string()
+                }
+            };
+
+            match node.node {
                 Node::File(siz) => {
                     write_indent(f, depth)?;
-                    write!(f, "{} (size={})\n", name, siz)?;
+                    write!(f, "{} (size={})\n", name, size(*siz))?;
                 }
-                Node::Dir(entries) => {
+                Node::Dir(_) => {
                     write_indent(f, depth)?;
                     write!(f, "{}/", name)?;
 
-                    write!(f, " (size={})", node.size())?;
+                    write!(f, " (size={})", size(node.size))?;
 
                     write!(f, "\n")?;
 
-                    for (name, file) in entries {
-                        disp(f, file, name, depth + 1)?;
+                    for (name, file) in &node.entries {
+                        disp(f, tree, file, name, depth + 1)?;
                     }
                 }
             };
             Ok(())
         }
 
-        disp(f, self, "", 0)
+        disp(f, self, &self.root, "", 0)
+    }
+}
+
+impl Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", Tree::new(self, false))
+    }
+}
+
+fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Minimal JSON reader, accepting only what `Node::to_json` produces:
+/// objects, strings and non-negative integers
+struct JsonParser<'a> {
+    chars: Peekable<std::str::CharIndices<'a>>,
+}
+
+impl<'a> JsonParser<'a> {
+    fn skip_whitespace(&mut self) {
+        while let Some((_, c)) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.chars.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            Some((i, c)) => Err(format!("at {}: expected '{}', got '{}'", i, expected, c)),
+            None => Err(format!("expected '{}', got end of input", expected)),
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(s),
+                Some((i, '\\')) => match self.chars.next() {
+                    Some((_, '"')) => s.push('"'),
+                    Some((_, '\\')) => s.push('\\'),
+                    Some((_, '/')) => s.push('/'),
+                    Some((_, 'n')) => s.push('\n'),
+                    Some((_, 'r')) => s.push('\r'),
+                    Some((_, 't')) => s.push('\t'),
+                    Some((_, 'u')) => {
+                        let hex: String = (0..4)
+                            .filter_map(|_| self.chars.next())
+                            .map(|(_, c)| c)
+                            .collect();
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(std::char::from_u32)
+                            .ok_or(format!("at {}: invalid escape \\u{}", i, hex))?;
+                        s.push(c);
+                    }
+                    _ => return Err(format!("at {}: invalid escape", i)),
+                },
+                Some((_, c)) => s.push(c),
+                None => return Err("unterminated string".to_owned()),
+            }
+        }
+    }
+
+    fn parse_node(&mut self) -> Result<Node, String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some((_, '{')) => {
+                self.chars.next();
+                let mut entries = HashMap::new();
+                self.skip_whitespace();
+                if let Some((_, '}')) = self.chars.peek() {
+                    self.chars.next();
+                    return Ok(Node::Dir(entries));
+                }
+                loop {
+                    let name = self.parse_string()?;
+                    self.expect(':')?;
+                    let node = self.parse_node()?;
+                    if entries.insert(name.clone(), node).is_some() {
+                        return Err(format!("duplicate entry: {}", name));
+                    }
+                    self.skip_whitespace();
+                    match self.chars.next() {
+                        Some((_, ',')) => continue,
+                        Some((_, '}')) => return Ok(Node::Dir(entries)),
+                        Some((i, c)) => return Err(format!("at {}: unexpected '{}'", i, c)),
+                        None => return Err("unterminated object".to_owned()),
+                    }
+                }
+            }
+            Some(&(i, c)) if c.is_ascii_digit() => {
+                let mut digits = String::new();
+                while let Some(&(_, c)) = self.chars.peek() {
+                    if !c.is_ascii_digit() {
+                        break;
+                    }
+                    digits.push(c);
+                    self.chars.next();
+                }
+                digits
+                    .parse()
+                    .map(Node::File)
+                    .map_err(|e| format!("at {}: {}", i, e))
+            }
+            Some((i, c)) => Err(format!("at {}: unexpected '{}'", i, c)),
+            None => Err("unexpected end of input".to_owned()),
+        }
+    }
+}
+
+/// Interactive shell for exploring a parsed filesystem
+struct Shell {
+    root: Node,
+    path: Vec<String>,
+}
+
+fn format_path(path: &[String]) -> String {
+    format!("/{}", path.join("/"))
+}
+
+impl Shell {
+    fn new(root: Node) -> Shell {
+        Shell { root, path: vec![] }
+    }
+
+    /// Turns an absolute or relative path into a list of names starting from root
+    fn resolve(&self, arg: &str) -> Result<Vec<String>, String> {
+        let mut path = if arg.starts_with('/') {
+            vec![]
+        } else {
+            self.path.clone()
+        };
+
+        for part in arg.split('/').filter(|p| !p.is_empty()) {
+            match part {
+                "." => {}
+                ".." => {
+                    if path.pop().is_none() {
+                        return Err(format!("{}: goes above root", arg));
+                    }
+                }
+                name => path.push(name.to_owned()),
+            }
+        }
+
+        Ok(path)
+    }
+
+    fn resolve_existing(&self, arg: &str) -> Result<(Vec<String>, &Node), String> {
+        let path = self.resolve(arg)?;
+        match self.root.find_path(&path) {
+            Some(node) => Ok((path, node)),
+            None => Err(format!("{}: no such file or directory", arg)),
+        }
+    }
+
+    /// Runs a single command line, returning its output
+    fn execute(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        match words[..] {
+            [] => Ok(String::new()),
+            ["pwd"] => Ok(format_path(&self.path)),
+            ["cd"] => {
+                self.path.clear();
+                Ok(String::new())
+            }
+            ["cd", arg] => {
+                let (path, node) = self.resolve_existing(arg)?;
+                if !node.is_dir() {
+                    return Err(format!("{}: not a directory", arg));
+                }
+                self.path = path;
+                Ok(String::new())
+            }
+            ["ls"] => self.execute("ls ."),
+            ["ls", arg] => {
+                let (_, node) = self.resolve_existing(arg)?;
+                if !node.is_dir() {
+                    return Ok(format!("{} {}", node.size(), arg));
+                }
+                let lines: Vec<String> = node
+                    .sorted_entries()
+                    .into_iter()
+                    .map(|(name, entry)| match entry {
+                        Node::File(size) => format!("{} {}", size, name),
+                        Node::Dir(_) => format!("dir {}", name),
+                    })
+                    .collect();
+                Ok(lines.join("\n"))
+            }
+            ["du"] => self.execute("du ."),
+            ["du", arg] => {
+                let (path, node) = self.resolve_existing(arg)?;
+                Ok(format!("{} {}", node.size(), format_path(&path)))
+            }
+            ["tree"] => self.execute("tree ."),
+            ["tree", "-h"] => self.execute("tree -h ."),
+            ["tree", arg] | ["tree", "-h", arg] => {
+                let (_, node) = self.resolve_existing(arg)?;
+                let tree = Tree::new(node, words.len() == 3);
+                Ok(format!("{}", tree).trim_end().to_owned())
+            }
+            ["find", "-size", size] => self.find_by_size(".", size),
+            ["find", arg, "-size", size] => self.find_by_size(arg, size),
+            ["rm", arg] => self.remove(arg, false),
+            ["rm", "-r", arg] => self.remove(arg, true),
+            ["mkdir", arg] => {
+                let mut path = self.resolve(arg)?;
+                let name = path.pop().ok_or(format!("{}: already exists", arg))?;
+                let parent = self
+                    .root
+                    .find_path_mut(&path)
+                    .and_then(|node| node.entries_mut())
+                    .ok_or(format!("{}: parent is not a directory", arg))?;
+                if parent.contains_key(&name) {
+                    return Err(format!("{}: already exists", arg));
+                }
+                parent.insert(name, Node::new_dir());
+                Ok(String::new())
+            }
+            _ => Err(format!("unknown command: {}", line)),
+        }
+    }
+
+    /// Lists everything below `arg` with size above `+N`, below `-N` or equal to `N`
+    fn find_by_size(&self, arg: &str, size: &str) -> Result<String, String> {
+        let (path, node) = self.resolve_existing(arg)?;
+
+        let (ordering, number) = match size.chars().next() {
+            Some('+') => (std::cmp::Ordering::Greater, &size[1..]),
+            Some('-') => (std::cmp::Ordering::Less, &size[1..]),
+            _ => (std::cmp::Ordering::Equal, size),
+        };
+        let number: usize = number
+            .parse()
+            .map_err(|_| format!("invalid size: {}", size))?;
+
+        fn walk(
+            node: &SizedNode,
+            path: &mut Vec<String>,
+            matches: &mut Vec<String>,
+            filter: &dyn Fn(usize) -> bool,
+        ) {
+            if filter(node.size) {
+                let suffix = if node.node.is_dir() && !path.is_empty() {
+                    "/"
+                } else {
+                    ""
+                };
+                matches.push(format!("{} {}{}", node.size, format_path(path), suffix));
+            }
+            for (name, entry) in &node.entries {
+                path.push((*name).clone());
+                walk(entry, path, matches, filter);
+                path.pop();
+            }
+        }
+
+        let mut matches = vec![];
+        walk(&node.sized(), &mut path.clone(), &mut matches, &|s| {
+            s.cmp(&number) == ordering
+        });
+        Ok(matches.join("\n"))
+    }
+
+    fn remove(&mut self, arg: &str, recursive: bool) -> Result<String, String> {
+        let (mut path, node) = self.resolve_existing(arg)?;
+        if node.is_dir() && !recursive {
+            return Err(format!("{}: is a directory", arg));
+        }
+        if self.path.starts_with(&path) {
+            return Err(format!("{}: contains the current directory", arg));
+        }
+
+        let name = path.pop().unwrap();
+        self.root
+            .find_path_mut(&path)
+            .and_then(|parent| parent.entries_mut())
+            .unwrap()
+            .remove(&name);
+        Ok(String::new())
+    }
+
+    fn run<R: BufRead>(&mut self, input: R) {
+        print!("$ ");
+        io::stdout().flush().unwrap();
+
+        for line in input.lines() {
+            let line = line.unwrap();
+            if line.trim() == "exit" {
+                break;
+            }
+
+            match self.execute(&line) {
+                Ok(output) if output.is_empty() => {}
+                Ok(output) => println!("{}", output),
+                Err(err) => println!("error: {}", err),
+            }
+
+            print!("$ ");
+            io::stdout().flush().unwrap();
+        }
+        println!();
+    }
+}
+
+/// Totals of the file lengths below every directory, walking the disk separately from
+/// `Node::from_disk` so entries it skips are still counted. Keyed by paths formatted like
+/// `Node::directories`, symlinks are not followed and unreadable entries count as empty.
+fn disk_totals(dir: &Path, path: &mut Vec<String>, out: &mut HashMap<String, usize>) -> usize {
+    let mut total = 0;
+
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            path.push(entry.file_name().to_string_lossy().into_owned());
+            total += disk_totals(&entry.path(), path, out);
+            path.pop();
+        } else if !file_type.is_symlink() {
+            total += entry
+                .metadata()
+                .map_or(0, |metadata| metadata.len() as usize);
+        }
+    }
+
+    out.insert(format_path(path), total);
+    total
+}
+
+/// Prints a transcript of `dir`, parses it back and compares the directory sizes with
+/// totals read from disk independently, reporting every directory that differs. The
+/// totals are file lengths, as with `du --apparent-size -b` without the size of the
+/// directories themselves.
+fn generate_transcript(dir: &str, breadth_first: bool, redundant_ls: bool) {
+    let (disk_root, skipped) = match Node::from_disk(Path::new(dir)) {
+        Ok(read) => read,
+        Err(err) => {
+            eprintln!("cannot read {}: {}", dir, err);
+            process::exit(1);
+        }
+    };
+    let transcript = disk_root.to_transcript(breadth_first, redundant_ls);
+
+    for line in &transcript {
+        println!("{}", line);
+    }
+
+    for path in &skipped {
+        eprintln!("skipped {}", path);
+    }
+
+    let parsed = match Node::from_command_lines(&mut transcript.into_iter().peekable()) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("generated transcript does not parse:\n{}", err);
+            process::exit(1);
+        }
+    };
+
+    let mut on_disk = HashMap::new();
+    disk_totals(Path::new(dir), &mut vec![], &mut on_disk);
+
+    let mut differences = 0;
+    for (path, size) in parsed.directories() {
+        match on_disk.remove(&path) {
+            Some(disk_size) if disk_size == size => {}
+            Some(disk_size) => {
+                eprintln!("{}: transcript {}, disk {}", path, size, disk_size);
+                differences += 1;
+            }
+            None => {
+                eprintln!("{}: transcript {}, not on disk", path, size);
+                differences += 1;
+            }
+        }
+    }
+    let mut missing: Vec<(String, usize)> = on_disk.into_iter().collect();
+    missing.sort();
+    for (path, disk_size) in missing {
+        eprintln!("{}: not in transcript, disk {}", path, disk_size);
+        differences += 1;
+    }
+
+    if differences == 0 {
+        eprintln!("parsed transcript matches the directory totals on disk");
+    } else {
+        eprintln!(
+            "parsed transcript differs from the directory totals on disk in {} directories",
+            differences
+        );
     }
 }
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    let option = |name: &str| {
+        args.iter()
+            .position(|a| a == name)
+            .map(|i| args.get(i + 1).expect("option expects a value").clone())
+    };
+
+    if let Some(dir) = option("--generate") {
+        let breadth_first = match option("--order").as_deref() {
+            None | Some("dfs") => false,
+            Some("bfs") => true,
+            Some(other) => panic!("unknown traversal order: {}", other),
+        };
+        generate_transcript(
+            &dir,
+            breadth_first,
+            args.iter().any(|a| a == "--redundant-ls"),
+        );
+        return;
+    }
+
     // let filename = "test.in";
     let filename = "my.in";
     let input = fs::read_to_string(filename).unwrap();
-    let lines = input
-        .split('\n')
-        .filter(|s| !s.is_empty())
-        .map(|s| s.to_owned())
-        .peekable();
+    let lines = input.split('\n').map(|s| s.to_owned()).peekable();
+
+    if args.iter().any(|a| a == "--check") {
+        let (_, diagnostics) = Node::parse_command_lines(lines);
+        for diagnostic in &diagnostics {
+            println!("{}", diagnostic);
+        }
+        println!("{} problems found", diagnostics.len());
+        return;
+    }
+
+    let root = match option("--import-json") {
+        Some(json_filename) => Node::from_json(&fs::read_to_string(json_filename).unwrap())
+            .expect("JSON must parse correctly"),
+        None => match Node::from_command_lines(&mut lines.clone()) {
+            Ok(root) => root,
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        },
+    };
+
+    if args.iter().any(|a| a == "--tree") {
+        let tree = Tree::new(&root, args.iter().any(|a| a == "-h"));
+        print!("{}", tree);
+        return;
+    }
+
+    if args.iter().any(|a| a == "--cleanup") {
+        let number = |name: &str, default: usize| {
+            option(name).map_or(default, |n| n.parse().expect("option expects a number"))
+        };
+        let disk_size = number("--disk-size", 70000000);
+        let required_space = number("--free", 30000000);
+        let protected: Vec<Vec<String>> = args
+            .windows(2)
+            .filter(|w| w[0] == "--protect")
+            .map(|w| {
+                w[1].split('/')
+                    .filter(|p| !p.is_empty())
+                    .map(|p| p.to_owned())
+                    .collect()
+            })
+            .collect();
 
-    let root = Node::from_command_lines(&mut lines.clone()).expect("commands must parse correctly");
+        let free_space = disk_size.saturating_sub(root.size());
+        let space_to_free = required_space.saturating_sub(free_space);
+
+        println!("Cleanup:");
+        match root.plan_cleanup(space_to_free, &protected) {
+            Some(chosen) => {
+                for (path, size) in &chosen {
+                    println!("{} {}", size, path);
+                }
+                let total: usize = chosen.iter().map(|(_, size)| size).sum();
+                println!("total {} of {} needed", total, space_to_free);
+            }
+            None => println!(
+                "cannot free {} without touching protected paths",
+                space_to_free
+            ),
+        }
+        return;
+    }
+
+    if let Some(json_filename) = option("--export-json") {
+        fs::write(json_filename, root.to_json() + "\n").unwrap();
+        return;
+    }
+
+    if env::args().any(|a| a == "--shell") {
+        let stdin = io::stdin();
+        Shell::new(root).run(stdin.lock());
+        return;
+    }
 
     println!("Part 1:");
     println!("{}", root.sum_all_directory_sizes_below(100000));
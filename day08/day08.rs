@@ -1,120 +1,576 @@
-fn get_visible_matrix(grid: &Vec<Vec<u8>>) -> Vec<Vec<bool>> {
-    let height = grid.len();
-    let width = grid[0].len();
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
 
-    let mut visible: Vec<Vec<bool>> = grid
-        .iter()
-        .map(|row| row.iter().map(|_| false).collect())
-        .collect();
+/// Direction in which a tree looks
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
 
-    for y in 0..height {
-        let mut highest = -1;
-        for x in 0..width {
-            if highest < grid[y][x] as i8 {
-                visible[y][x] = true;
-                highest = grid[y][x] as i8;
-            }
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+];
+
+impl Direction {
+    /// Position in `DIRECTIONS`
+    fn index(&self) -> usize {
+        match self {
+            Direction::Up => 0,
+            Direction::Down => 1,
+            Direction::Left => 2,
+            Direction::Right => 3,
         }
-        let mut highest = -1;
-        for x in (0..width).rev() {
-            if highest < grid[y][x] as i8 {
-                visible[y][x] = true;
-                highest = grid[y][x] as i8;
-            }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::Left => "left",
+            Direction::Right => "right",
         }
     }
 
-    for x in 0..width {
-        let mut highest = -1;
-        for y in 0..height {
-            if highest < grid[y][x] as i8 {
-                visible[y][x] = true;
-                highest = grid[y][x] as i8;
-            }
+    fn step(&self) -> (isize, isize) {
+        match self {
+            Direction::Up => (-1, 0),
+            Direction::Down => (1, 0),
+            Direction::Left => (0, -1),
+            Direction::Right => (0, 1),
         }
-        let mut highest = -1;
-        for y in (0..height).rev() {
-            if highest < grid[y][x] as i8 {
-                visible[y][x] = true;
-                highest = grid[y][x] as i8;
-            }
+    }
+}
+
+/// Trees scanned so far along a line, starting at the edge the trees look at.
+///
+/// Keeps a stack of trees that are not hidden behind a later tree at least as tall, so
+/// the top of the stack, after popping lower trees, is the one blocking the view.
+struct LineOfSight {
+    stack: Vec<(u8, usize)>,
+    len: usize,
+}
+
+impl LineOfSight {
+    fn new() -> LineOfSight {
+        LineOfSight {
+            stack: vec![],
+            len: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.stack.clear();
+        self.len = 0;
+    }
+
+    /// Adds the next tree, returning whether it sees the edge and its viewing distance
+    fn push(&mut self, tree: u8) -> (bool, usize) {
+        while self.stack.last().is_some_and(|&(h, _)| h < tree) {
+            self.stack.pop();
+        }
+
+        let i = self.len;
+        let result = match self.stack.last() {
+            Some(&(_, blocker)) => (false, i - blocker),
+            None => (true, i),
+        };
+
+        // A tree of the same height blocks everything the one before it did
+        if self.stack.last().is_some_and(|&(h, _)| h == tree) {
+            self.stack.pop();
+        }
+        self.stack.push((tree, i));
+        self.len += 1;
+        result
+    }
+}
+
+/// Calls `visit(direction, y, x, visible_from_edge, viewing_distance)` for every tree and
+/// every direction.
+///
+/// Rows are scanned in both directions one at a time. Columns keep one line of sight each
+/// and are scanned together, so the grid is always read row by row.
+fn scan_lines_of_sight<F>(grid: &[Vec<u8>], mut visit: F)
+where
+    F: FnMut(Direction, usize, usize, bool, usize),
+{
+    let mut line_of_sight = LineOfSight::new();
+
+    for (y, row) in grid.iter().enumerate() {
+        line_of_sight.clear();
+        for (x, &tree) in row.iter().enumerate() {
+            let (visible_from_edge, distance) = line_of_sight.push(tree);
+            visit(Direction::Left, y, x, visible_from_edge, distance);
+        }
+
+        line_of_sight.clear();
+        for (x, &tree) in row.iter().enumerate().rev() {
+            let (visible_from_edge, distance) = line_of_sight.push(tree);
+            visit(Direction::Right, y, x, visible_from_edge, distance);
         }
     }
 
-    visible
+    let mut columns: Vec<LineOfSight> = grid[0].iter().map(|_| LineOfSight::new()).collect();
+
+    for (y, row) in grid.iter().enumerate() {
+        for (x, (&tree, column)) in row.iter().zip(columns.iter_mut()).enumerate() {
+            let (visible_from_edge, distance) = column.push(tree);
+            visit(Direction::Up, y, x, visible_from_edge, distance);
+        }
+    }
+
+    columns.iter_mut().for_each(LineOfSight::clear);
+    for (y, row) in grid.iter().enumerate().rev() {
+        for (x, (&tree, column)) in row.iter().zip(columns.iter_mut()).enumerate() {
+            let (visible_from_edge, distance) = column.push(tree);
+            visit(Direction::Down, y, x, visible_from_edge, distance);
+        }
+    }
+}
+
+/// Finds the trees visible from an edge and the scenic score of every tree in a single
+/// scan of the grid
+fn get_visible_matrix_and_scenic_scores(grid: &[Vec<u8>]) -> (Vec<Vec<bool>>, Vec<Vec<usize>>) {
+    let mut visible: Vec<Vec<bool>> = grid.iter().map(|row| vec![false; row.len()]).collect();
+    let mut score: Vec<Vec<usize>> = grid.iter().map(|row| vec![1; row.len()]).collect();
+
+    scan_lines_of_sight(grid, |_, y, x, visible_from_edge, distance| {
+        visible[y][x] |= visible_from_edge;
+        score[y][x] *= distance;
+    });
+
+    (visible, score)
 }
 
-fn count_true_in_grid(grid: &Vec<Vec<bool>>) -> usize {
+fn count_true_in_grid(grid: &[Vec<bool>]) -> usize {
     grid.iter()
         .map(|row| row.iter().filter(|s| **s).count())
         .sum()
 }
 
-fn get_scenic_scores(grid: &Vec<Vec<u8>>) -> Vec<Vec<usize>> {
-    let height = grid.len();
-    let width = grid[0].len();
+fn max_in_grid(grid: &[Vec<usize>]) -> usize {
+    grid.iter()
+        .map(|row| *row.iter().max().unwrap_or(&0))
+        .max()
+        .unwrap_or(0)
+}
 
-    let mut score: Vec<Vec<usize>> = grid
-        .iter()
-        .map(|row| row.iter().map(|_| 1).collect())
-        .collect();
+/// Walks from the tree until the view is blocked
+fn viewing_distance(grid: &[Vec<u8>], y: usize, x: usize, dir: Direction) -> usize {
+    let (dy, dx) = dir.step();
+    let (mut cy, mut cx) = (y as isize, x as isize);
+    let mut distance = 0;
 
-    let mut running: Vec<u8> = vec![];
+    loop {
+        cy += dy;
+        cx += dx;
+        if cy < 0 || cx < 0 {
+            return distance;
+        }
+        let tree = match grid.get(cy as usize).and_then(|row| row.get(cx as usize)) {
+            Some(tree) => *tree,
+            None => return distance,
+        };
+        distance += 1;
+        if tree >= grid[y][x] {
+            return distance;
+        }
+    }
+}
 
-    for y in 0..height {
-        running.clear();
-        for x in 0..width {
-            score[y][x] *= running
-                .iter()
-                .rev()
-                .position(|h| (*h) >= grid[y][x])
-                .map(|p| p + 1)
-                .unwrap_or(running.len());
-            running.push(grid[y][x]);
-        }
-        running.clear();
-        for x in (0..width).rev() {
-            score[y][x] *= running
-                .iter()
-                .rev()
-                .position(|h| (*h) >= grid[y][x])
-                .map(|p| p + 1)
-                .unwrap_or(running.len());
-            running.push(grid[y][x]);
+/// Returns `(score, y, x)` of the `n` highest scores, best first
+fn top_scenic_spots(scores: &[Vec<usize>], n: usize) -> Vec<(usize, usize, usize)> {
+    let mut heap = BinaryHeap::new();
+
+    for (y, row) in scores.iter().enumerate() {
+        for (x, score) in row.iter().enumerate() {
+            heap.push(Reverse((*score, Reverse(y), Reverse(x))));
+            if heap.len() > n {
+                heap.pop();
+            }
         }
     }
 
-    for x in 0..width {
-        running.clear();
-        for y in 0..height {
-            score[y][x] *= running
-                .iter()
-                .rev()
-                .position(|h| (*h) >= grid[y][x])
-                .map(|p| p + 1)
-                .unwrap_or(running.len());
-            running.push(grid[y][x]);
-        }
-        running.clear();
-        for y in (0..height).rev() {
-            score[y][x] *= running
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse((score, Reverse(y), Reverse(x)))| (score, y, x))
+        .collect()
+}
+
+fn write_csv<T: ToString>(filename: &str, grid: &[Vec<T>]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(filename)?);
+    for row in grid {
+        let cells: Vec<String> = row.iter().map(|c| c.to_string()).collect();
+        writeln!(out, "{}", cells.join(","))?;
+    }
+    out.flush()
+}
+
+/// Writes visible trees as white pixels of a binary PGM image
+fn write_visible_pgm(filename: &str, visible: &[Vec<bool>]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(filename)?);
+    write!(out, "P5\n{} {}\n255\n", visible[0].len(), visible.len())?;
+    for row in visible {
+        let pixels: Vec<u8> = row.iter().map(|&v| if v { 255 } else { 0 }).collect();
+        out.write_all(&pixels)?;
+    }
+    out.flush()
+}
+
+/// Maps `t` from 0 to 1 onto a black, blue, red, yellow, white gradient
+fn heat_color(t: f64) -> [u8; 3] {
+    let stops = [
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 255.0],
+        [255.0, 0.0, 0.0],
+        [255.0, 255.0, 0.0],
+        [255.0, 255.0, 255.0],
+    ];
+    let position = t.clamp(0.0, 1.0) * (stops.len() - 1) as f64;
+    let i = (position as usize).min(stops.len() - 2);
+    let fraction = position - i as f64;
+
+    let mut color = [0; 3];
+    for c in 0..3 {
+        color[c] = (stops[i][c] + (stops[i + 1][c] - stops[i][c]) * fraction).round() as u8;
+    }
+    color
+}
+
+/// Writes scenic scores as a binary PPM heatmap, on a logarithmic scale
+fn write_scenic_ppm(filename: &str, scores: &[Vec<usize>]) -> io::Result<()> {
+    let max = (max_in_grid(scores) as f64 + 1.0).ln();
+
+    let mut out = BufWriter::new(File::create(filename)?);
+    write!(out, "P6\n{} {}\n255\n", scores[0].len(), scores.len())?;
+    for row in scores {
+        let pixels: Vec<u8> = row
+            .iter()
+            .flat_map(|&s| {
+                let t = if max > 0.0 {
+                    (s as f64 + 1.0).ln() / max
+                } else {
+                    0.0
+                };
+                heat_color(t).to_vec()
+            })
+            .collect();
+        out.write_all(&pixels)?;
+    }
+    out.flush()
+}
+
+/// Candidate treehouse position with viewing distances in `DIRECTIONS` order
+struct Spot {
+    y: usize,
+    x: usize,
+    score: usize,
+    distances: [usize; 4],
+}
+
+impl Spot {
+    fn distance(&self, dir: Direction) -> usize {
+        self.distances[dir.index()]
+    }
+
+    fn sees(&self, other: &Spot) -> bool {
+        if self.y == other.y && other.x < self.x {
+            self.distance(Direction::Left) >= self.x - other.x
+        } else if self.y == other.y && other.x > self.x {
+            self.distance(Direction::Right) >= other.x - self.x
+        } else if self.x == other.x && other.y < self.y {
+            self.distance(Direction::Up) >= self.y - other.y
+        } else if self.x == other.x && other.y > self.y {
+            self.distance(Direction::Down) >= other.y - self.y
+        } else {
+            false
+        }
+    }
+}
+
+/// Chooses `k` positions with the highest total scenic score such that no treehouse sees
+/// another one. Returns the total and the chosen spots.
+///
+/// Branch and bound over positions ordered by score, bounding each branch by the sum of
+/// the best scores still available. Gives up after trying `limit` partial placements.
+fn place_treehouses(
+    grid: &[Vec<u8>],
+    scores: &[Vec<usize>],
+    k: usize,
+    limit: usize,
+) -> Result<(usize, Vec<Spot>), String> {
+    let mut distances = vec![vec![[0; 4]; grid[0].len()]; grid.len()];
+    scan_lines_of_sight(grid, |dir, y, x, _, distance| {
+        distances[y][x][dir.index()] = distance;
+    });
+
+    let mut spots: Vec<Spot> = vec![];
+    for (y, row) in scores.iter().enumerate() {
+        for (x, score) in row.iter().enumerate() {
+            spots.push(Spot {
+                y,
+                x,
+                score: *score,
+                distances: distances[y][x],
+            });
+        }
+    }
+    spots.sort_by_key(|spot| Reverse(spot.score));
+
+    let mut prefix_sums = vec![0; spots.len() + 1];
+    for (i, spot) in spots.iter().enumerate() {
+        prefix_sums[i + 1] = prefix_sums[i] + spot.score;
+    }
+
+    let mut search = TreehouseSearch {
+        spots: &spots,
+        prefix_sums,
+        k,
+        chosen: vec![],
+        best: None,
+        states: 0,
+        limit,
+    };
+    if !search.search(0, 0) {
+        return Err(format!(
+            "no placement of {} treehouses proven best after trying {} partial placements",
+            k, limit
+        ));
+    }
+
+    let (total, chosen) = match search.best {
+        Some(best) => best,
+        None => {
+            return Err(format!(
+                "cannot place {} treehouses out of sight of each other",
+                k
+            ))
+        }
+    };
+    let chosen_spots = spots
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| chosen.binary_search(i).is_ok())
+        .map(|(_, spot)| spot)
+        .collect();
+    Ok((total, chosen_spots))
+}
+
+struct TreehouseSearch<'a> {
+    spots: &'a [Spot],
+    prefix_sums: Vec<usize>,
+    k: usize,
+    /// Indices into `spots`, in increasing order
+    chosen: Vec<usize>,
+    best: Option<(usize, Vec<usize>)>,
+    states: usize,
+    limit: usize,
+}
+
+impl<'a> TreehouseSearch<'a> {
+    /// Tries every spot from `start` on as the next treehouse. Returns false once more
+    /// than `limit` partial placements were tried.
+    fn search(&mut self, start: usize, total: usize) -> bool {
+        let spots = self.spots;
+        if self.chosen.len() == self.k {
+            if self.best.as_ref().is_none_or(|(b, _)| total > *b) {
+                self.best = Some((total, self.chosen.clone()));
+            }
+            return true;
+        }
+
+        let missing = self.k - self.chosen.len();
+        for (i, spot) in spots.iter().enumerate().skip(start) {
+            if i + missing > spots.len() {
+                break;
+            }
+            let bound = total + self.prefix_sums[i + missing] - self.prefix_sums[i];
+            if self.best.as_ref().is_some_and(|(b, _)| bound <= *b) {
+                break;
+            }
+
+            if self
+                .chosen
                 .iter()
-                .rev()
-                .position(|h| (*h) >= grid[y][x])
-                .map(|p| p + 1)
-                .unwrap_or(running.len());
-            running.push(grid[y][x]);
+                .all(|&c| !spot.sees(&spots[c]) && !spots[c].sees(spot))
+            {
+                self.states += 1;
+                if self.states > self.limit {
+                    return false;
+                }
+                self.chosen.push(i);
+                let finished = self.search(i + 1, total + spot.score);
+                self.chosen.pop();
+                if !finished {
+                    return false;
+                }
+            }
         }
+        true
     }
+}
 
-    score
+/// Stack of equally sized height layers
+struct Volume {
+    depth: usize,
+    height: usize,
+    width: usize,
+    cells: Vec<u8>,
 }
 
-fn max_in_grid(grid: &Vec<Vec<usize>>) -> usize {
-    grid.iter()
-        .map(|row| *row.iter().max().unwrap_or(&0))
-        .max()
-        .unwrap_or(0)
+impl Volume {
+    /// Parses digit grids, one per layer, separated by empty lines
+    fn parse_layers(input: &str) -> Result<Volume, String> {
+        let layers: Vec<Vec<Vec<u8>>> = input
+            .split("\n\n")
+            .map(|layer| {
+                layer
+                    .split('\n')
+                    .filter(|s| !s.is_empty())
+                    .map(|s| {
+                        s.chars()
+                            .map(|c| {
+                                c.to_digit(10)
+                                    .map(|d| d as u8)
+                                    .ok_or_else(|| format!("'{}' is not a digit", c))
+                            })
+                            .collect::<Result<Vec<u8>, String>>()
+                    })
+                    .collect::<Result<Vec<Vec<u8>>, String>>()
+            })
+            .collect::<Result<Vec<Vec<Vec<u8>>>, String>>()?
+            .into_iter()
+            .filter(|layer| !layer.is_empty())
+            .collect();
+
+        let depth = layers.len();
+        let height = layers.first().map_or(0, |layer| layer.len());
+        let width = layers
+            .first()
+            .and_then(|layer| layer.first())
+            .map_or(0, |row| row.len());
+
+        if depth == 0 || width == 0 {
+            return Err("volume is empty".to_owned());
+        }
+        for (z, layer) in layers.iter().enumerate() {
+            if layer.len() != height || layer.iter().any(|row| row.len() != width) {
+                return Err(format!("layer {} is not {}x{}", z + 1, width, height));
+            }
+        }
+
+        Ok(Volume {
+            depth,
+            height,
+            width,
+            cells: layers.into_iter().flatten().flatten().collect(),
+        })
+    }
+
+    fn index(&self, z: usize, y: usize, x: usize) -> usize {
+        (z * self.height + y) * self.width + x
+    }
+
+    /// Moves `(z, y, x)` by `dir`, if the result is still inside the volume
+    fn step(
+        &self,
+        (z, y, x): (usize, usize, usize),
+        dir: [isize; 3],
+    ) -> Option<(usize, usize, usize)> {
+        let moved = |pos: usize, d: isize, len: usize| {
+            let next = pos as isize + d;
+            if next >= 0 && (next as usize) < len {
+                Some(next as usize)
+            } else {
+                None
+            }
+        };
+        Some((
+            moved(z, dir[0], self.depth)?,
+            moved(y, dir[1], self.height)?,
+            moved(x, dir[2], self.width)?,
+        ))
+    }
+
+    /// Lines of sight along the axes, and with `diagonal` also along every diagonal.
+    /// Axes along which the volume is flat are left out.
+    fn directions(&self, diagonal: bool) -> Vec<[isize; 3]> {
+        let extents = [self.depth, self.height, self.width];
+        let mut dirs = vec![];
+
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let dir = [dz, dy, dx];
+                    let moving_axes = dir.iter().filter(|&&d| d != 0).count();
+                    let is_flat = (0..3).any(|axis| dir[axis] != 0 && extents[axis] == 1);
+                    if moving_axes == 0 || is_flat || (moving_axes > 1 && !diagonal) {
+                        continue;
+                    }
+                    dirs.push(dir);
+                }
+            }
+        }
+
+        dirs
+    }
+
+    /// Calls `visit(index, visible_from_edge, viewing_distance)` for every cell and every
+    /// direction in `dirs`
+    fn scan_lines_of_sight<F>(&self, dirs: &[[isize; 3]], mut visit: F)
+    where
+        F: FnMut(usize, bool, usize),
+    {
+        let mut line_of_sight = LineOfSight::new();
+
+        for &dir in dirs {
+            let back = [-dir[0], -dir[1], -dir[2]];
+
+            for z in 0..self.depth {
+                for y in 0..self.height {
+                    for x in 0..self.width {
+                        // lines start at the cells next to the edge the cells look at
+                        if self.step((z, y, x), dir).is_some() {
+                            continue;
+                        }
+
+                        line_of_sight.clear();
+                        let mut cell = Some((z, y, x));
+                        while let Some((cz, cy, cx)) = cell {
+                            let index = self.index(cz, cy, cx);
+                            let (visible_from_edge, distance) =
+                                line_of_sight.push(self.cells[index]);
+                            visit(index, visible_from_edge, distance);
+                            cell = self.step((cz, cy, cx), back);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn visible(&self, dirs: &[[isize; 3]]) -> Vec<bool> {
+        let mut visible = vec![false; self.cells.len()];
+        self.scan_lines_of_sight(dirs, |i, visible_from_edge, _| {
+            visible[i] |= visible_from_edge
+        });
+        visible
+    }
+
+    fn scenic_scores(&self, dirs: &[[isize; 3]]) -> Vec<usize> {
+        let mut scores = vec![1; self.cells.len()];
+        self.scan_lines_of_sight(dirs, |i, _, distance| scores[i] *= distance);
+        scores
+    }
 }
 
 fn main() {
@@ -122,19 +578,85 @@ fn main() {
     let filepath = "my.in";
     let input = std::fs::read_to_string(filepath).unwrap();
 
+    let args: Vec<String> = env::args().collect();
+    let option = |name: &str| {
+        args.iter()
+            .position(|a| a == name)
+            .map(|i| args.get(i + 1).expect("option expects a value").clone())
+    };
+
+    if let Some(volume_filepath) = option("--volume") {
+        let volume =
+            Volume::parse_layers(&std::fs::read_to_string(volume_filepath).unwrap()).unwrap();
+        let dirs = volume.directions(args.iter().any(|a| a == "--diagonal"));
+
+        println!("Visible:");
+        println!("{}", volume.visible(&dirs).iter().filter(|v| **v).count());
+
+        println!("Best scenic score:");
+        println!("{}", volume.scenic_scores(&dirs).iter().max().unwrap());
+        return;
+    }
+
     let grid: Vec<Vec<u8>> = input
         .split('\n')
         .filter(|s| !s.is_empty())
         .map(|s| s.chars().map(|c| c as u8 - '0' as u8).collect::<Vec<u8>>())
         .collect();
 
-    let visible = get_visible_matrix(&grid);
+    let (visible, scenic_scores) = get_visible_matrix_and_scenic_scores(&grid);
 
     println!("Part 1:");
     println!("{:?}", count_true_in_grid(&visible));
 
-    let scenic_scores = get_scenic_scores(&grid);
-
     println!("Part 2:");
     println!("{:?}", max_in_grid(&scenic_scores));
+
+    if let Some(prefix) = option("--export") {
+        write_visible_pgm(&format!("{}-visible.pgm", prefix), &visible).unwrap();
+        let visible_numbers: Vec<Vec<u8>> = visible
+            .iter()
+            .map(|row| row.iter().map(|&v| v as u8).collect())
+            .collect();
+        write_csv(&format!("{}-visible.csv", prefix), &visible_numbers).unwrap();
+        write_scenic_ppm(&format!("{}-scenic.ppm", prefix), &scenic_scores).unwrap();
+        write_csv(&format!("{}-scenic.csv", prefix), &scenic_scores).unwrap();
+    }
+
+    if let Some(k) = option("--place") {
+        let k: usize = k.parse().expect("--place expects a number");
+        let limit = option("--place-limit").map_or(1_000_000, |n| {
+            n.parse().expect("--place-limit expects a number")
+        });
+
+        println!("Treehouse placement:");
+        match place_treehouses(&grid, &scenic_scores, k, limit) {
+            Ok((total, spots)) => {
+                for spot in &spots {
+                    println!("row {} column {}: score {}", spot.y, spot.x, spot.score);
+                }
+                println!("total {}", total);
+            }
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    if let Some(n) = option("--top") {
+        let n: usize = n.parse().expect("--top expects a number");
+
+        println!("Best treehouse locations:");
+        for (score, y, x) in top_scenic_spots(&scenic_scores, n) {
+            let distances: Vec<String> = DIRECTIONS
+                .iter()
+                .map(|dir| format!("{} {}", dir.name(), viewing_distance(&grid, y, x, *dir)))
+                .collect();
+            println!(
+                "row {} column {}: score {} ({})",
+                y,
+                x,
+                score,
+                distances.join(", ")
+            );
+        }
+    }
 }
@@ -1,12 +1,180 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
+use std::env;
 
-type Point = (i32, i32);
+type Point = (i64, i64);
+
+/// Family of parallel lines: every cell lies on the line `key(cell)`, at `param(cell)`
+#[derive(Clone, Copy)]
+enum LineFamily {
+    Horizontal,
+    Vertical,
+    /// Lines going up and right
+    Rising,
+    /// Lines going down and right
+    Falling,
+}
+
+const LINE_FAMILIES: [LineFamily; 4] = [
+    LineFamily::Horizontal,
+    LineFamily::Vertical,
+    LineFamily::Rising,
+    LineFamily::Falling,
+];
+
+impl LineFamily {
+    /// Family of the line going through a cell in direction `(dx, dy)`, along which
+    /// `param` grows
+    fn along(dx: i64, dy: i64) -> LineFamily {
+        match (dx, dy) {
+            (_, 0) => LineFamily::Horizontal,
+            (0, _) => LineFamily::Vertical,
+            _ if dx == dy => LineFamily::Rising,
+            _ => LineFamily::Falling,
+        }
+    }
+
+    fn key(&self, (x, y): Point) -> i64 {
+        match self {
+            LineFamily::Horizontal => y,
+            LineFamily::Vertical => x,
+            LineFamily::Rising => x - y,
+            LineFamily::Falling => x + y,
+        }
+    }
+
+    fn param(&self, (x, y): Point) -> i64 {
+        match self {
+            LineFamily::Vertical => y,
+            _ => x,
+        }
+    }
+
+    fn point(&self, key: i64, param: i64) -> Point {
+        match self {
+            LineFamily::Horizontal => (param, key),
+            LineFamily::Vertical => (key, param),
+            LineFamily::Rising => (param, param - key),
+            LineFamily::Falling => (param, key - param),
+        }
+    }
+}
+
+/// Set of cells stored as straight segments. Every family maps a line key to disjoint,
+/// non-adjacent `start -> end` parameter ranges.
+#[derive(Clone)]
+struct SegmentSet {
+    lines: [BTreeMap<i64, BTreeMap<i64, i64>>; 4],
+}
+
+impl SegmentSet {
+    fn new() -> SegmentSet {
+        SegmentSet {
+            lines: [
+                BTreeMap::new(),
+                BTreeMap::new(),
+                BTreeMap::new(),
+                BTreeMap::new(),
+            ],
+        }
+    }
+
+    fn insert(&mut self, p: Point) {
+        self.insert_segment(p, (1, 0), 1);
+    }
+
+    /// Inserts `len` cells starting at `from` and going in direction `dir`
+    fn insert_segment(&mut self, from: Point, dir: Point, len: i64) {
+        if len <= 0 {
+            return;
+        }
+        let to = (from.0 + dir.0 * (len - 1), from.1 + dir.1 * (len - 1));
+        let family = LineFamily::along(dir.0, dir.1);
+        let (a, b) = (family.param(from), family.param(to));
+        let (mut start, mut end) = (a.min(b), a.max(b));
+
+        let ranges = self.lines[family as usize]
+            .entry(family.key(from))
+            .or_default();
+
+        let touching: Vec<(i64, i64)> = ranges
+            .range(..=end + 1)
+            .rev()
+            .take_while(|(_, &e)| e >= start - 1)
+            .map(|(&s, &e)| (s, e))
+            .collect();
+        for (s, e) in touching {
+            ranges.remove(&s);
+            start = start.min(s);
+            end = end.max(e);
+        }
+        ranges.insert(start, end);
+    }
+
+    fn family_contains(&self, family: LineFamily, p: Point) -> bool {
+        let t = family.param(p);
+        self.lines[family as usize]
+            .get(&family.key(p))
+            .and_then(|ranges| ranges.range(..=t).next_back())
+            .is_some_and(|(_, &end)| end >= t)
+    }
+
+    fn contains(&self, p: &Point) -> bool {
+        LINE_FAMILIES.iter().any(|f| self.family_contains(*f, *p))
+    }
+
+    /// Counts the cells, correcting for cells where segments of different families cross
+    fn len(&self) -> u64 {
+        let mut total: u64 = 0;
+        let mut crossings: HashSet<Point> = HashSet::new();
+
+        for (i, family) in LINE_FAMILIES.iter().enumerate() {
+            for (&key, ranges) in &self.lines[i] {
+                for (&start, &end) in ranges {
+                    total += (end - start + 1) as u64;
+
+                    for other in LINE_FAMILIES.iter().skip(i + 1) {
+                        // the other key changes linearly along this segment
+                        let base = other.key(family.point(key, 0));
+                        let slope = other.key(family.point(key, 1)) - base;
+                        let (k1, k2) = (base + slope * start, base + slope * end);
+
+                        for (&other_key, _) in
+                            self.lines[*other as usize].range(k1.min(k2)..=k1.max(k2))
+                        {
+                            if (other_key - base) % slope != 0 {
+                                continue;
+                            }
+                            let p = family.point(key, (other_key - base) / slope);
+                            if self.family_contains(*other, p) {
+                                crossings.insert(p);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        for p in crossings {
+            let families = LINE_FAMILIES
+                .iter()
+                .filter(|f| self.family_contains(**f, p))
+                .count();
+            total -= families as u64 - 1;
+        }
+
+        total
+    }
+}
 
 enum Direction {
     Up,
     Down,
     Left,
     Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
 }
 
 impl Direction {
@@ -17,74 +185,219 @@ impl Direction {
             "D" => Ok(Down),
             "L" => Ok(Left),
             "R" => Ok(Right),
+            "UL" => Ok(UpLeft),
+            "UR" => Ok(UpRight),
+            "DL" => Ok(DownLeft),
+            "DR" => Ok(DownRight),
             _ => Err(()),
         }
     }
+
+    fn delta(&self) -> Point {
+        use Direction::*;
+        match self {
+            Up => (0, 1),
+            Down => (0, -1),
+            Left => (-1, 0),
+            Right => (1, 0),
+            UpLeft => (-1, 1),
+            UpRight => (1, 1),
+            DownLeft => (-1, -1),
+            DownRight => (1, -1),
+        }
+    }
 }
 
 struct Rope {
     head: Point,
     tail: Vec<Point>,
-    visited: HashSet<Point>,
+    /// Maximum distance between each knot and the one before it
+    link_lengths: Vec<i64>,
+    /// Cells visited by every knot, starting with the head
+    visited: Vec<SegmentSet>,
 }
 
 impl Rope {
     fn new(n: usize) -> Rope {
+        Rope::with_link_lengths(vec![1; n - 1])
+    }
+
+    fn with_link_lengths(link_lengths: Vec<i64>) -> Rope {
+        let knot_count = link_lengths.len() + 1;
+        let mut visited = SegmentSet::new();
+        visited.insert((0, 0));
         Rope {
             head: (0, 0),
-            tail: vec![(0, 0); n - 1],
-            visited: HashSet::from([(0, 0)]),
+            tail: vec![(0, 0); link_lengths.len()],
+            link_lengths,
+            visited: vec![visited; knot_count],
         }
     }
 
+    fn tail_visited(&self) -> &SegmentSet {
+        self.visited.last().unwrap()
+    }
+
+    /// Moves step by step until a step shifts every knot by the same amount as the head.
+    /// The rope keeps its shape after such a step, so the rest of the move shifts it in
+    /// one go.
     fn applyn(&mut self, dir: &Direction, n: usize) {
-        for _ in 0..n {
-            self.apply(&dir);
-            // self.print_state((-5,5), (-5,5));
+        let (dx, dy) = dir.delta();
+        let mut remaining = n as i64;
+
+        while remaining > 0 {
+            let before = self.tail.clone();
+            self.apply(dir);
+            remaining -= 1;
+
+            let is_rigid = self
+                .tail
+                .iter()
+                .zip(&before)
+                .all(|(after, before)| *after == (before.0 + dx, before.1 + dy));
+            if is_rigid {
+                break;
+            }
+        }
+
+        if remaining > 0 {
+            let knots = std::iter::once(&mut self.head).chain(self.tail.iter_mut());
+            for (knot, visited) in knots.zip(self.visited.iter_mut()) {
+                visited.insert_segment((knot.0 + dx, knot.1 + dy), (dx, dy), remaining);
+                knot.0 += dx * remaining;
+                knot.1 += dy * remaining;
+            }
         }
     }
 
     fn apply(&mut self, dir: &Direction) {
-        match dir {
-            Direction::Up => self.head.1 += 1,
-            Direction::Down => self.head.1 -= 1,
-            Direction::Left => self.head.0 -= 1,
-            Direction::Right => self.head.0 += 1,
-        }
+        let (dx, dy) = dir.delta();
+        self.head.0 += dx;
+        self.head.1 += dy;
+        self.visited[0].insert(self.head);
 
         for i in 0..(self.tail.len()) {
             let head = if i != 0 { self.tail[i - 1] } else { self.head };
+            let length = self.link_lengths[i];
             let tail = &mut self.tail[i];
 
-            while (head.0 - tail.0).abs() > 1 || (head.1 - tail.1).abs() > 1 {
+            while (head.0 - tail.0).abs() > length || (head.1 - tail.1).abs() > length {
                 let dx = (head.0 - tail.0).signum();
                 let dy = (head.1 - tail.1).signum();
                 tail.0 += dx;
                 tail.1 += dy;
+                self.visited[i + 1].insert(*tail);
             }
         }
+    }
+
+    fn knots(&self) -> Vec<Point> {
+        std::iter::once(self.head)
+            .chain(self.tail.iter().cloned())
+            .collect()
+    }
+}
+
+/// Positions of all the knots after every single step, starting with the initial state.
+/// Moves are not shifted in bulk here, so the trace grows with the total move count.
+fn record_trace(link_lengths: Vec<i64>, moves: &[(Direction, usize)]) -> Vec<Vec<Point>> {
+    let mut rope = Rope::with_link_lengths(link_lengths);
+    let mut frames = vec![rope.knots()];
+
+    for (direction, count) in moves {
+        for _ in 0..*count {
+            rope.apply(direction);
+            frames.push(rope.knots());
+        }
+    }
+
+    frames
+}
 
-        self.visited.insert(*self.tail.last().unwrap());
+/// Smallest `(min, max)` corners containing every position in `frames`
+fn bounding_box(frames: &[Vec<Point>]) -> (Point, Point) {
+    let mut min = (0, 0);
+    let mut max = (0, 0);
+    for p in frames.iter().flatten() {
+        min = (min.0.min(p.0), min.1.min(p.1));
+        max = (max.0.max(p.0), max.1.max(p.1));
     }
+    (min, max)
+}
+
+/// Renders a frame with the head as `H`, other knots by number, the start as `o`
+/// and cells visited by the tail as `#`
+fn render_frame(knots: &[Point], visited: &SegmentSet, (min, max): (Point, Point)) -> String {
+    let mut out = String::new();
+    for y in (min.1..=max.1).rev() {
+        for x in min.0..=max.0 {
+            let c = match knots.iter().position(|p| *p == (x, y)) {
+                Some(0) => 'H',
+                Some(idx) => std::char::from_digit((idx % 10) as u32, 10).unwrap(),
+                None if (x, y) == (0, 0) => 'o',
+                None if visited.contains(&(x, y)) => '#',
+                None => '.',
+            };
+            out.push(c);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Writes a frame as a binary PPM image, drawing every cell as a `scale` pixels square
+fn write_frame_ppm(
+    filename: &str,
+    knots: &[Point],
+    visited: &SegmentSet,
+    (min, max): (Point, Point),
+    scale: usize,
+) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let width = (max.0 - min.0 + 1) as usize;
+    let height = (max.1 - min.1 + 1) as usize;
+    let mut out = std::io::BufWriter::new(std::fs::File::create(filename)?);
+    write!(out, "P6\n{} {}\n255\n", width * scale, height * scale)?;
 
-    fn print_state(&self, x_range: (i32, i32), y_range: (i32, i32)) {
-        for y in (y_range.0)..=(y_range.1) {
-            for x in (x_range.0)..=(x_range.1) {
-                if (x, y) == self.head {
-                    print!("H");
-                } else if let Some(idx) = self.tail.iter().position(|p| *p == (x, y)) {
-                    print!("{}", idx + 1);
-                } else if x == 0 && y == 0 {
-                    print!("o");
-                } else if self.visited.contains(&(x, y)) {
-                    print!("#");
-                } else {
-                    print!(".");
+    for y in (min.1..=max.1).rev() {
+        let mut row: Vec<u8> = vec![];
+        for x in min.0..=max.0 {
+            let color: [u8; 3] = match knots.iter().position(|p| *p == (x, y)) {
+                Some(0) => [230, 40, 40],
+                Some(idx) => {
+                    let fade = (200 * idx / knots.len()) as u8;
+                    [250 - fade, 160 - fade / 2, 40]
                 }
+                None if (x, y) == (0, 0) => [40, 200, 80],
+                None if visited.contains(&(x, y)) => [110, 110, 130],
+                None => [20, 20, 30],
+            };
+            for _ in 0..scale {
+                row.extend_from_slice(&color);
             }
-            print!("\n");
+        }
+        for _ in 0..scale {
+            out.write_all(&row)?;
         }
     }
+
+    out.flush()
+}
+
+fn parse_moves(input: &str) -> Vec<(Direction, usize)> {
+    input
+        .split('\n')
+        .filter(|s| !s.is_empty())
+        .map(|line| {
+            let (direction, count) = line.split_once(' ').unwrap();
+
+            let direction = Direction::from_str(direction).unwrap();
+            let count: usize = count.parse().unwrap();
+
+            (direction, count)
+        })
+        .collect()
 }
 
 fn main() {
@@ -92,37 +405,102 @@ fn main() {
     // let filename = "test2.in";
     let filename = "my.in";
     let input = std::fs::read_to_string(filename).unwrap();
+    let moves = parse_moves(&input);
 
     {
         let mut rope = Rope::new(2);
 
-        for line in input.split('\n').filter(|s| !s.is_empty()) {
-            let (direction, count) = line.split_once(' ').unwrap();
-
-            let direction = Direction::from_str(direction).unwrap();
-            let count: usize = count.parse().unwrap();
-
-            rope.applyn(&direction, count);
+        for (direction, count) in &moves {
+            rope.applyn(direction, *count);
         }
 
         println!("Part 1:");
-        println!("{}", rope.visited.len());
+        println!("{}", rope.tail_visited().len());
     }
 
     {
         let mut rope = Rope::new(10);
 
-        for line in input.split('\n').filter(|s| !s.is_empty()) {
-            let (direction, count) = line.split_once(' ').unwrap();
+        for (direction, count) in &moves {
+            rope.applyn(direction, *count);
+        }
 
-            let direction = Direction::from_str(direction).unwrap();
-            let count: usize = count.parse().unwrap();
+        println!("Part 2:");
+        println!("{}", rope.tail_visited().len());
+    }
+
+    let args: Vec<String> = env::args().collect();
+    let option = |name: &str| {
+        args.iter()
+            .position(|a| a == name)
+            .map(|i| args.get(i + 1).expect("option expects a value").clone())
+    };
 
-            rope.applyn(&direction, count);
-            // rope.print_state((-20, 20), (-20, 20));
+    let link_lengths: Option<Vec<i64>> = match (option("--knots"), option("--link-lengths")) {
+        (_, Some(lengths)) => {
+            let lengths: Vec<i64> = lengths
+                .split(',')
+                .filter(|l| !l.is_empty())
+                .map(|l| {
+                    l.parse::<u32>()
+                        .map(i64::from)
+                        .expect("link lengths must be non-negative numbers")
+                })
+                .collect();
+            assert!(!lengths.is_empty(), "rope needs at least 1 link");
+            Some(lengths)
         }
+        (Some(knots), None) => {
+            let knots: usize = knots.parse().expect("--knots expects a number");
+            assert!(knots >= 2, "rope needs at least 2 knots");
+            Some(vec![1; knots - 1])
+        }
+        (None, None) => None,
+    };
 
-        println!("Part 2:");
-        println!("{}", rope.visited.len());
+    let trace_images = option("--trace-images");
+    if args.iter().any(|a| a == "--trace") || trace_images.is_some() {
+        let frames = record_trace(link_lengths.clone().unwrap_or(vec![1; 9]), &moves);
+        let bounds = bounding_box(&frames);
+        let delay: u64 =
+            option("--delay").map_or(50, |d| d.parse().expect("--delay expects milliseconds"));
+        let mut visited = SegmentSet::new();
+
+        for (i, knots) in frames.iter().enumerate() {
+            visited.insert(*knots.last().unwrap());
+
+            match &trace_images {
+                Some(prefix) => {
+                    let filename = format!("{}{:05}.ppm", prefix, i);
+                    write_frame_ppm(&filename, knots, &visited, bounds, 4).unwrap();
+                }
+                None => {
+                    // clear the terminal and move the cursor to the top left corner
+                    print!("\x1b[2J\x1b[H");
+                    print!("{}", render_frame(knots, &visited, bounds));
+                    println!("step {}/{}", i, frames.len() - 1);
+                    std::thread::sleep(std::time::Duration::from_millis(delay));
+                }
+            }
+        }
+        return;
+    }
+
+    if let Some(link_lengths) = link_lengths {
+        let mut rope = Rope::with_link_lengths(link_lengths);
+
+        for (direction, count) in &moves {
+            rope.applyn(direction, *count);
+        }
+
+        println!("Visited cells:");
+        for (i, visited) in rope.visited.iter().enumerate() {
+            let name = if i == 0 {
+                "H".to_owned()
+            } else {
+                i.to_string()
+            };
+            println!("{}: {}", name, visited.len());
+        }
     }
 }
@@ -1,67 +1,584 @@
-use std::str::FromStr;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Register {
+    X,
+    A,
+    B,
+    C,
+    D,
+}
+
+const REGISTERS: [(&str, Register); 5] = [
+    ("x", Register::X),
+    ("a", Register::A),
+    ("b", Register::B),
+    ("c", Register::C),
+    ("d", Register::D),
+];
+
+impl Register {
+    fn name(&self) -> &'static str {
+        REGISTERS.iter().find(|(_, r)| r == self).unwrap().0
+    }
+
+    fn from_str(s: &str) -> Option<Register> {
+        REGISTERS
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, r)| *r)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    Value(i32),
+    Register(Register),
+}
+
+impl Operand {
+    fn from_str(s: &str) -> Option<Operand> {
+        match Register::from_str(s) {
+            Some(r) => Some(Operand::Register(r)),
+            None => s.parse().ok().map(Operand::Value),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Value(v) => write!(f, "{}", v),
+            Operand::Register(r) => write!(f, "{}", r.name()),
+        }
+    }
+}
+
+/// Jump targets are indices of instructions in the program
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum Instruction {
     Noop,
     AddX(i32),
+    Add(Register, Operand),
+    Set(Register, Operand),
+    Jump(usize),
+    JumpIfZero(Register, usize),
+    JumpIfNotZero(Register, usize),
 }
 
-impl FromStr for Instruction {
-    type Err = ();
+/// Number of cycles every instruction takes to complete
+const CYCLE_COSTS: [(&str, usize); 7] = [
+    ("noop", 1),
+    ("addx", 2),
+    ("add", 2),
+    ("set", 1),
+    ("jmp", 1),
+    ("jz", 2),
+    ("jnz", 2),
+];
+
+impl Instruction {
+    fn mnemonic(&self) -> &'static str {
+        use Instruction::*;
+        match self {
+            Noop => "noop",
+            AddX(_) => "addx",
+            Add(_, _) => "add",
+            Set(_, _) => "set",
+            Jump(_) => "jmp",
+            JumpIfZero(_, _) => "jz",
+            JumpIfNotZero(_, _) => "jnz",
+        }
+    }
+
+    fn cycles(&self) -> usize {
+        let mnemonic = self.mnemonic();
+        CYCLE_COSTS.iter().find(|(m, _)| *m == mnemonic).unwrap().1
+    }
+
+    fn jump_target(&self) -> Option<usize> {
+        use Instruction::*;
+        match self {
+            Jump(target) | JumpIfZero(_, target) | JumpIfNotZero(_, target) => Some(*target),
+            _ => None,
+        }
+    }
+
+    /// Formats the instruction, naming jump targets with `label`
+    fn display(&self, label: impl Fn(usize) -> String) -> String {
+        use Instruction::*;
+        let mnemonic = self.mnemonic();
+        match self {
+            Noop => mnemonic.to_owned(),
+            AddX(v) => format!("{} {}", mnemonic, v),
+            Add(r, o) | Set(r, o) => format!("{} {} {}", mnemonic, r.name(), o),
+            Jump(target) => format!("{} {}", mnemonic, label(*target)),
+            JumpIfZero(r, target) | JumpIfNotZero(r, target) => {
+                format!("{} {} {}", mnemonic, r.name(), label(*target))
+            }
+        }
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
+    fn parse(s: &str, labels: &HashMap<String, usize>) -> Result<Self, String> {
         use Instruction::*;
-        match s.split(' ').collect::<Vec<&str>>()[..] {
+
+        let register =
+            |name: &str| Register::from_str(name).ok_or(format!("unknown register: {}", name));
+        let operand = |s: &str| Operand::from_str(s).ok_or(format!("invalid operand: {}", s));
+        let label = |name: &str| {
+            labels
+                .get(name)
+                .cloned()
+                .ok_or(format!("unknown label: {}", name))
+        };
+
+        match s.split_whitespace().collect::<Vec<&str>>()[..] {
             ["noop"] => Ok(Noop),
-            ["addx", v] => Ok(AddX(v.parse::<i32>().unwrap())),
-            _ => Err(()),
+            ["addx", v] => v.parse::<i32>().map(AddX).map_err(|e| e.to_string()),
+            ["add", r, o] => Ok(Add(register(r)?, operand(o)?)),
+            ["set", r, o] => Ok(Set(register(r)?, operand(o)?)),
+            ["jmp", l] => Ok(Jump(label(l)?)),
+            ["jz", r, l] => Ok(JumpIfZero(register(r)?, label(l)?)),
+            ["jnz", r, l] => Ok(JumpIfNotZero(register(r)?, label(l)?)),
+            _ => Err(format!("unknown instruction: {}", s)),
+        }
+    }
+}
+
+/// Parses instructions, one per line. Lines like `name:` label the next instruction,
+/// everything after `;` is a comment.
+fn parse_program(input: &str) -> Result<Vec<Instruction>, String> {
+    let lines: Vec<(usize, &str)> = input
+        .split('\n')
+        .enumerate()
+        .map(|(i, s)| (i + 1, s.split(';').next().unwrap().trim()))
+        .filter(|(_, s)| !s.is_empty())
+        .collect();
+
+    let mut labels: HashMap<String, usize> = HashMap::new();
+    let mut instruction_count = 0;
+    for (line_number, line) in &lines {
+        match line.strip_suffix(':') {
+            Some(label) => {
+                if labels.insert(label.to_owned(), instruction_count).is_some() {
+                    return Err(format!("line {}: duplicate label {}", line_number, label));
+                }
+            }
+            None => instruction_count += 1,
         }
     }
+
+    lines
+        .iter()
+        .filter(|(_, line)| !line.ends_with(':'))
+        .map(|(line_number, line)| {
+            Instruction::parse(line, &labels).map_err(|e| format!("line {}: {}", line_number, e))
+        })
+        .collect()
 }
 
 struct CPU {
-    register_x: i32,
+    registers: [i32; 5],
     instructions: Vec<Instruction>,
+    program_counter: usize,
+    /// Cycles left until the current instruction completes
+    remaining_cycles: usize,
     cycle_count: usize,
 }
 
 impl CPU {
     fn from_instructions(insts: &Vec<Instruction>) -> Self {
-        let instructions = insts
-            .iter()
-            .flat_map(|i| match i {
-                Instruction::Noop => vec![Instruction::Noop],
-                Instruction::AddX(v) => vec![Instruction::Noop, Instruction::AddX(*v)],
-            })
-            .collect();
+        let mut registers = [0; 5];
+        registers[Register::X as usize] = 1;
         Self {
-            register_x: 1,
-            instructions,
+            registers,
+            instructions: insts.clone(),
+            program_counter: 0,
+            remaining_cycles: 0,
             cycle_count: 0,
         }
     }
+
+    fn register(&self, r: Register) -> i32 {
+        self.registers[r as usize]
+    }
+
+    fn value(&self, o: Operand) -> i32 {
+        match o {
+            Operand::Value(v) => v,
+            Operand::Register(r) => self.register(r),
+        }
+    }
+
+    fn execute(&mut self, instruction: Instruction) {
+        use Instruction::*;
+
+        self.program_counter += 1;
+        match instruction {
+            Noop => {}
+            AddX(v) => self.registers[Register::X as usize] += v,
+            Add(r, o) => self.registers[r as usize] += self.value(o),
+            Set(r, o) => self.registers[r as usize] = self.value(o),
+            Jump(target) => self.program_counter = target,
+            JumpIfZero(r, target) => {
+                if self.register(r) == 0 {
+                    self.program_counter = target;
+                }
+            }
+            JumpIfNotZero(r, target) => {
+                if self.register(r) != 0 {
+                    self.program_counter = target;
+                }
+            }
+        }
+    }
 }
 
 impl Iterator for CPU {
     type Item = (usize, i32);
 
+    /// Runs a single cycle, returning its number and the value of X during it
     fn next(&mut self) -> Option<Self::Item> {
-        if self.cycle_count >= self.instructions.len() {
-            return None;
+        if self.remaining_cycles == 0 {
+            let instruction = self.instructions.get(self.program_counter)?;
+            self.remaining_cycles = instruction.cycles();
+        }
+
+        let old_x = self.register(Register::X);
+
+        self.cycle_count += 1;
+        self.remaining_cycles -= 1;
+        if self.remaining_cycles == 0 {
+            self.execute(self.instructions[self.program_counter]);
+        }
+
+        Some((self.cycle_count, old_x))
+    }
+}
+
+const CRT_WIDTH: usize = 40;
+const CRT_HEIGHT: usize = 6;
+const GLYPH_WIDTH: usize = 4;
+/// Glyphs are separated by a single empty column
+const GLYPH_SPACING: usize = GLYPH_WIDTH + 1;
+
+/// Letters drawn by the CRT, rows from top to bottom
+const FONT: [(char, [&str; CRT_HEIGHT]); 17] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+type Framebuffer = Vec<Vec<bool>>;
+
+/// Draws `CRT_HEIGHT` rows of `CRT_WIDTH` pixels, lit where the sprite covers the beam
+fn render_crt(cpu: CPU) -> Framebuffer {
+    let mut framebuffer = vec![vec![false; CRT_WIDTH]; CRT_HEIGHT];
+
+    for (cycle, x) in cpu.take(CRT_WIDTH * CRT_HEIGHT) {
+        let row = (cycle - 1) / CRT_WIDTH;
+        let column = (cycle - 1) % CRT_WIDTH;
+        framebuffer[row][column] = (x - column as i32).abs() <= 1;
+    }
+
+    framebuffer
+}
+
+/// Glyph that matches no letter of the font, with its position counted from 0
+#[derive(Debug)]
+struct UnknownGlyph {
+    position: usize,
+    rows: Vec<String>,
+}
+
+#[derive(Debug)]
+struct OcrError {
+    unknown: Vec<UnknownGlyph>,
+}
+
+impl fmt::Display for OcrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} unrecognized glyphs", self.unknown.len())?;
+        for glyph in &self.unknown {
+            write!(f, "\nglyph {}:", glyph.position)?;
+            for row in &glyph.rows {
+                write!(f, "\n{}", row)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the letters drawn on the framebuffer. Empty glyphs are read as spaces, except
+/// at the end.
+fn decode_framebuffer(framebuffer: &Framebuffer) -> Result<String, OcrError> {
+    let width = framebuffer.first().map_or(0, |row| row.len());
+    let glyph_count = (width + 1) / GLYPH_SPACING;
+
+    let mut text = String::new();
+    let mut unknown = vec![];
+
+    for position in 0..glyph_count {
+        let columns = position * GLYPH_SPACING..position * GLYPH_SPACING + GLYPH_WIDTH;
+        let rows: Vec<String> = framebuffer
+            .iter()
+            .map(|row| {
+                row[columns.clone()]
+                    .iter()
+                    .map(|&lit| if lit { '#' } else { '.' })
+                    .collect()
+            })
+            .collect();
+
+        let is_blank = rows.iter().all(|row| !row.contains('#'));
+        let letter = FONT
+            .iter()
+            .find(|(_, glyph)| {
+                rows.len() == CRT_HEIGHT && glyph.iter().zip(&rows).all(|(a, b)| a == b)
+            })
+            .map(|(letter, _)| *letter)
+            .or(is_blank.then_some(' '));
+
+        match letter {
+            Some(letter) => text.push(letter),
+            None => unknown.push(UnknownGlyph { position, rows }),
+        }
+    }
+
+    if unknown.is_empty() {
+        Ok(text.trim_end().to_owned())
+    } else {
+        Err(OcrError { unknown })
+    }
+}
+
+/// Most runs of an instruction noted in the disassembly
+const MAX_NOTES: usize = 8;
+
+/// Lists the program with generated labels, annotating every instruction with the cycles
+/// it runs in while the CRT is drawn, the value of X and the columns covered by the sprite
+fn disassemble(instructions: &Vec<Instruction>) -> String {
+    let pixel_count = CRT_WIDTH * CRT_HEIGHT;
+    // First cycle and value of X for every run of every instruction
+    let mut runs: Vec<Vec<(usize, i32)>> = vec![vec![]; instructions.len()];
+    let mut cpu = CPU::from_instructions(instructions);
+
+    while cpu.cycle_count < pixel_count {
+        let starting = cpu.program_counter;
+        let is_start = cpu.remaining_cycles == 0;
+        match cpu.next() {
+            Some((cycle, x)) if is_start => runs[starting].push((cycle, x)),
+            Some(_) => {}
+            None => break,
         }
+    }
 
-        let old_x = self.register_x;
+    let mut targets: Vec<usize> = instructions
+        .iter()
+        .filter_map(|i| i.jump_target())
+        .collect();
+    targets.sort();
+    targets.dedup();
+    let label = |target: usize| format!("l{}", target);
+
+    let mut listing = String::new();
+    for (i, instruction) in instructions.iter().enumerate() {
+        if targets.contains(&i) {
+            listing += &format!("{}:\n", label(i));
+        }
 
-        match self.instructions[self.cycle_count] {
-            Instruction::Noop => {}
-            Instruction::AddX(v) => {
-                self.register_x += v;
+        // Back to back runs with the same value of X, as in a loop, share one note
+        let mut spans: Vec<(usize, usize, i32)> = vec![];
+        for &(first, x) in &runs[i] {
+            let last = (first + instruction.cycles() - 1).min(pixel_count);
+            match spans.last_mut() {
+                Some((_, span_last, span_x)) if *span_x == x && *span_last + 1 == first => {
+                    *span_last = last
+                }
+                _ => spans.push((first, last, x)),
             }
         }
 
-        self.cycle_count += 1;
+        let mut notes: Vec<String> = spans
+            .iter()
+            .take(MAX_NOTES)
+            .map(|&(first, last, x)| {
+                let cycles = if first == last {
+                    format!("cycle {}", first)
+                } else {
+                    format!("cycles {}-{}", first, last)
+                };
+                format!("{}, x={}, sprite {}-{}", cycles, x, x - 1, x + 1)
+            })
+            .collect();
+        if spans.len() > MAX_NOTES {
+            notes.push(format!("... and {} more runs", spans.len() - MAX_NOTES));
+        }
+        let note = if notes.is_empty() {
+            "not reached".to_owned()
+        } else {
+            notes.join("; ")
+        };
 
-        Some((self.cycle_count, old_x))
+        listing += &format!("{:<16}; {}\n", instruction.display(label), note);
+    }
+    for target in targets.iter().filter(|&&t| t >= instructions.len()) {
+        listing += &format!("{}:\n", label(*target));
+    }
+
+    listing
+}
+
+/// Reads an image of `CRT_HEIGHT` rows of up to `CRT_WIDTH` pixels, where `#` marks lit
+/// pixels and missing ones are dark
+fn parse_image(input: &str) -> Result<Framebuffer, String> {
+    let rows: Vec<&str> = input.trim_end_matches('\n').split('\n').collect();
+    if rows.len() != CRT_HEIGHT {
+        return Err(format!(
+            "image has {} rows, expected {}",
+            rows.len(),
+            CRT_HEIGHT
+        ));
+    }
+
+    rows.iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let row = row.trim_end();
+            if row.chars().count() > CRT_WIDTH {
+                return Err(format!(
+                    "row {}: {} pixels, expected at most {}",
+                    i + 1,
+                    row.chars().count(),
+                    CRT_WIDTH
+                ));
+            }
+            let mut pixels: Vec<bool> = row.chars().map(|c| c == '#').collect();
+            pixels.resize(CRT_WIDTH, false);
+            Ok(pixels)
+        })
+        .collect()
+}
+
+/// Draws `text` with the CRT font, leaving spaces empty
+fn render_text(text: &str) -> Result<Framebuffer, String> {
+    let glyph_count = (CRT_WIDTH + 1) / GLYPH_SPACING;
+    if text.chars().count() > glyph_count {
+        return Err(format!("text is longer than {} letters", glyph_count));
+    }
+
+    let mut framebuffer = vec![vec![false; CRT_WIDTH]; CRT_HEIGHT];
+    for (position, letter) in text.chars().enumerate() {
+        if letter == ' ' {
+            continue;
+        }
+        let glyph = FONT
+            .iter()
+            .find(|(l, _)| *l == letter.to_ascii_uppercase())
+            .map(|(_, glyph)| glyph)
+            .ok_or(format!("letter {} is missing from the font", letter))?;
+
+        for (row, pixels) in glyph.iter().enumerate() {
+            for (column, pixel) in pixels.chars().enumerate() {
+                framebuffer[row][position * GLYPH_SPACING + column] = pixel == '#';
+            }
+        }
+    }
+
+    Ok(framebuffer)
+}
+
+/// Values of X worth considering, anything outside of them leaves every pixel dark too
+const X_RANGE: std::ops::RangeInclusive<i32> = -2..=CRT_WIDTH as i32;
+
+/// Instruction count of the shortest program reaching each state, with the previous value
+/// of X and the instruction taken from it
+type ProgramTable = Vec<Vec<Option<(usize, Option<(i32, Instruction)>)>>>;
+
+/// Finds the shortest `addx`/`noop` program drawing `framebuffer`.
+///
+/// X only changes at the end of an `addx`, so it is held for at least two cycles after
+/// every change, and it is 1 during the first cycle. The search goes cycle by cycle over
+/// the value of X at the start of each instruction.
+fn generate_program(framebuffer: &Framebuffer) -> Result<Vec<Instruction>, String> {
+    let pixel_count = CRT_WIDTH * CRT_HEIGHT;
+    let lit = |cycle: usize| framebuffer[cycle / CRT_WIDTH][cycle % CRT_WIDTH];
+    let fits = |cycle: usize, x: i32| {
+        cycle >= pixel_count || ((x - (cycle % CRT_WIDTH) as i32).abs() <= 1) == lit(cycle)
+    };
+    let index = |x: i32| (x - X_RANGE.start()) as usize;
+
+    // For every cycle and value of X, the instruction count and the step which got there
+    let mut best: ProgramTable = vec![vec![None; X_RANGE.count()]; pixel_count + 2];
+    best[0][index(1)] = Some((0, None));
+
+    let mut reached = 0;
+    for cycle in 0..pixel_count {
+        for x in X_RANGE {
+            let count = match best[cycle][index(x)] {
+                Some((count, _)) if fits(cycle, x) => count,
+                _ => continue,
+            };
+            reached = cycle + 1;
+
+            let mut offer = |cycle: usize, new_x: i32, instruction: Instruction| {
+                let entry = &mut best[cycle][index(new_x)];
+                if entry.is_none_or(|(c, _)| count + 1 < c) {
+                    *entry = Some((count + 1, Some((x, instruction))));
+                }
+            };
+
+            offer(cycle + 1, x, Instruction::Noop);
+            if fits(cycle + 1, x) {
+                for new_x in X_RANGE {
+                    offer(cycle + 2, new_x, Instruction::AddX(new_x - x));
+                }
+            }
+        }
+    }
+
+    let end = (pixel_count..pixel_count + 2)
+        .flat_map(|cycle| X_RANGE.map(move |x| (cycle, x)))
+        .filter_map(|(cycle, x)| best[cycle][index(x)].map(|(count, _)| (count, cycle, x)))
+        .min();
+
+    let (mut cycle, mut x) = match end {
+        Some((_, cycle, x)) => (cycle, x),
+        None => {
+            return Err(format!(
+                "pixel in row {}, column {} cannot be drawn",
+                reached / CRT_WIDTH + 1,
+                reached % CRT_WIDTH + 1
+            ))
+        }
+    };
+
+    let mut program = vec![];
+    while let Some((_, Some((old_x, instruction)))) = best[cycle][index(x)] {
+        program.push(instruction);
+        cycle -= instruction.cycles();
+        x = old_x;
     }
+    program.reverse();
+
+    Ok(program)
 }
 
 fn main() {
@@ -69,18 +586,14 @@ fn main() {
     let filename = "my.in";
     let input = std::fs::read_to_string(filename).unwrap();
 
-    let instructions: Vec<Instruction> = input
-        .split('\n')
-        .filter(|s| !s.is_empty())
-        .map(|s| s.parse::<Instruction>().unwrap())
-        .collect();
+    let instructions: Vec<Instruction> = parse_program(&input).unwrap();
 
     {
         let cpu = CPU::from_instructions(&instructions);
 
         let mut answer = 0;
 
-        for (cycle, x) in cpu {
+        for (cycle, x) in cpu.take(220) {
             if (cycle as i32 - 20) % 40 == 0 {
                 answer += cycle as i32 * x;
             }
@@ -92,22 +605,46 @@ fn main() {
 
     {
         let cpu = CPU::from_instructions(&instructions);
-
-        let mut cursor_x = 0;
+        let framebuffer = render_crt(cpu);
 
         println!("Part 2:");
-        for (_, x) in cpu {
-            if (x - cursor_x).abs() <= 1 {
-                print!("#");
-            } else {
-                print!(" ");
-            }
+        for row in &framebuffer {
+            let line: String = row.iter().map(|&lit| if lit { '#' } else { ' ' }).collect();
+            println!("{}", line);
+        }
+
+        match decode_framebuffer(&framebuffer) {
+            Ok(text) => println!("{}", text),
+            Err(err) => eprintln!("Could not read the CRT: {}", err),
+        }
+    }
+
+    let args: Vec<String> = env::args().collect();
+    let option = |name: &str| {
+        args.iter()
+            .position(|a| a == name)
+            .map(|i| args.get(i + 1).expect("option expects a value").clone())
+    };
+
+    if args.iter().any(|a| a == "--disassemble") {
+        println!("Disassembly:");
+        print!("{}", disassemble(&instructions));
+    }
 
-            cursor_x += 1;
-            if cursor_x == 40 {
-                cursor_x = 0;
-                print!("\n");
+    let target = match (option("--draw"), option("--draw-image")) {
+        (Some(text), _) => Some(render_text(&text)),
+        (None, Some(path)) => Some(parse_image(&fs::read_to_string(path).unwrap())),
+        (None, None) => None,
+    };
+    if let Some(target) = target {
+        match target.and_then(|framebuffer| generate_program(&framebuffer)) {
+            Ok(program) => {
+                println!("Program:");
+                for instruction in &program {
+                    println!("{}", instruction.display(|t| t.to_string()));
+                }
             }
+            Err(err) => eprintln!("Could not generate the program: {}", err),
         }
     }
 }